@@ -1,5 +1,6 @@
 #![no_std]
 #![allow(clippy::new_without_default)]
 
+pub mod service_enums;
 pub mod services;
 pub mod state;
@@ -0,0 +1,158 @@
+use sails_rs::prelude::*;
+use crate::state::{
+    Blocks,
+    JobId,
+    JobStatus,
+    JobStopReason,
+    PoolName
+};
+use gstd::errors::{
+    CoreError,
+    Error,
+    ExecutionError,
+    ExtError,
+    ReservationError
+};
+
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum GasReservationError {
+    ActionOnlyForAdmins,
+    ActionOnlyForAllowedAccounts,
+    ActionOnlyForProgram,
+    AdminAlreadyExists,
+    AdminDoesNotExists,
+    CanNotRemoveLastAdmin,
+    AccountAlreadyAllowed,
+    AccountIsNotAllowed,
+    ReservationDoesNotExists,
+    InvalidRefillPolicy,
+    RefillPolicyDoesNotExists,
+    NoReservationForJob,
+    JobDoesNotExists,
+    JobIsNotScheduled,
+    // The gas to reserve can not be 0
+    ZeroGas,
+    // The blocks of a reservation, delay or period can not be 0
+    ZeroBlocks,
+    // No more ids for reservations or jobs
+    IdOverflow,
+    // A label or pool name is longer than the limit of the service
+    LimitExceeded,
+    // The runtime could not reserve the gas
+    ReservationFailed(RuntimeErrorKind),
+    // The runtime could not send the wake-up message of a job
+    JobSchedulingFailed(RuntimeErrorKind),
+    // The handler of a recurring job can not be a route of this service
+    InvalidJobHandler,
+    // The wake-up message is not the one that the job expects (stale or forged)
+    InvalidWakeUp
+}
+
+// # Events of the gas reservation service
+// Sails events of the service, the events of the reservations are queued by the
+// state ("GasReservationState::queue_event") and emitted by the next command, so
+// they are emitted too when an extending service uses the reservations
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum GasReservationEvent {
+    ReservationCreated {
+        id: u64,
+        pool: PoolName,
+        amount: u64,
+        expires_at: Blocks
+    },
+    // The reservation was taken to be used
+    ReservationConsumed {
+        id: u64,
+        pool: PoolName
+    },
+    ExpiredReservationsRemoved {
+        pool: PoolName,
+        count: u32,
+        reclaimed_gas: u64
+    },
+    // The reservation was removed before its expiry
+    ReservationUnreserved {
+        id: u64,
+        pool: PoolName,
+        reclaimed_gas: u64
+    },
+    // The active reservations of the pool are below its low watermark
+    LowWatermark {
+        pool: PoolName,
+        active_reservations: u32,
+        threshold: u32
+    },
+    JobScheduled {
+        id: JobId,
+        target: ActorId,
+        dispatch_at: u32
+    },
+    JobDispatched {
+        id: JobId,
+        status: JobStatus
+    },
+    JobCancelled(JobId),
+    RecurringJobRegistered {
+        id: JobId,
+        period_blocks: u32,
+        next_run: u32
+    },
+    RecurringJobRun {
+        id: JobId,
+        next_run: Option<u32>
+    },
+    RecurringJobStopped {
+        id: JobId,
+        reason: JobStopReason
+    }
+}
+
+// # Kind of the gstd error returned by the runtime
+#[derive(Encode, Decode, TypeInfo, Clone, Copy, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum RuntimeErrorKind {
+    InvalidReservationId,
+    ReservationsLimitReached,
+    ZeroReservationDuration,
+    ZeroReservationAmount,
+    ReservationBelowMailboxThreshold,
+    NotEnoughGas,
+    NotEnoughValue,
+    // Other error of the runtime, with its code
+    Other(u32),
+    // Error of the gstd library (not of the runtime)
+    Gstd
+}
+
+impl From<CoreError> for RuntimeErrorKind {
+    fn from(error: CoreError) -> Self {
+        let CoreError::Ext(error) = error else {
+            return Self::Gstd;
+        };
+
+        match error {
+            ExtError::Reservation(ReservationError::InvalidReservationId) => Self::InvalidReservationId,
+            ExtError::Reservation(ReservationError::ReservationsLimitReached) => Self::ReservationsLimitReached,
+            ExtError::Reservation(ReservationError::ZeroReservationDuration) => Self::ZeroReservationDuration,
+            ExtError::Reservation(ReservationError::ZeroReservationAmount) => Self::ZeroReservationAmount,
+            ExtError::Reservation(ReservationError::ReservationBelowMailboxThreshold) => Self::ReservationBelowMailboxThreshold,
+            ExtError::Execution(ExecutionError::NotEnoughGas) => Self::NotEnoughGas,
+            ExtError::Execution(ExecutionError::NotEnoughValue) => Self::NotEnoughValue,
+            error => Self::Other(error.to_u32())
+        }
+    }
+}
+
+impl From<Error> for RuntimeErrorKind {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Core(error) => error.into(),
+            _ => Self::Gstd
+        }
+    }
+}
@@ -1,14 +1,33 @@
-use sails_rs::prelude::*;
+use sails_rs::{
+    prelude::*,
+    gstd::{exec, msg}
+};
 use crate::state	::{
     GasReservationState,
-    GasReservationData
+    GasReservationData,
+    ActiveGasReservation,
+    PoolName,
+    PoolStatus,
+    RefillPolicy,
+    RefillReport,
+    BatchReport,
+    Job,
+    JobId,
+    JobStatus,
+    ScheduledCall,
+    JobHealth,
+    RecurringJobStatus,
+    ConsumptionLogPage
+};
+use crate::service_enums::{
+    GasReservationError,
+    GasReservationEvent
 };
-use vara_contract_utils::utils;
 
 #[derive(Default, Clone)]
 pub struct GasReservationService;
 
-#[service]
+#[service(events = GasReservationEvent)]
 impl GasReservationService {
     // Service "Constructor"
     pub fn new() -> Self {
@@ -16,31 +35,419 @@ impl GasReservationService {
     }
 
     // Function to initialize the service state, call only once
-    // in the "program" constructor
+    // in the "program" constructor (the caller will be the first admin)
     pub fn seed() {
         GasReservationState::init_state();
     }
 
-    // Command to reserve gas
-    pub fn new_gas_reservation(&mut self, gas_amount: u64, blocks: u32) -> ContractResponse {
-        utils::panicking(|| {
-            let result = GasReservationState::state_mut()
-                .create_reservation(gas_amount, blocks);
+    // Command to reserve gas, with an optional label to identify the
+    // reservation (only admins and allowed accounts)
+    pub fn new_gas_reservation(&mut self, gas_amount: u64, blocks: u32, label: Option<String>) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.can_manage_reservations(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAllowedAccounts);
+        }
+
+        let response = match state.create_reservation(gas_amount, blocks, label) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(_) => ContractResponse::ReservationCreated
+        };
+
+        self.emit_events(None);
+
+        response
+    }
+
+    // Command to reserve gas in a named pool, the reservations of a pool can only be
+    // taken from that pool (only admins and allowed accounts)
+    pub fn new_pool_gas_reservation(
+        &mut self,
+        pool: PoolName,
+        gas_amount: u64,
+        blocks: u32,
+        label: Option<String>
+    ) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.can_manage_reservations(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAllowedAccounts);
+        }
+
+        let response = match state.create_pool_reservation(&pool, gas_amount, blocks, label) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(_) => ContractResponse::PoolReservationCreated(pool)
+        };
+
+        self.emit_events(None);
+
+        response
+    }
+
+    // Command to take the oldest active reservation of a named pool, the reservation
+    // is consumed (stored in the consumption log with the optional tag) and its id
+    // and gas are returned (only admins and allowed accounts)
+    pub fn take_pool_reservation(&mut self, pool: PoolName, tag: Option<String>) -> ContractResponse {
+        if !GasReservationState::state_ref().can_manage_reservations(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAllowedAccounts);
+        }
 
-            if let Err(e) = result {
-                return Err(e);
+        let response = match GasReservationState::lease_pool_reservation(&pool) {
+            None => ContractResponse::Error(GasReservationError::ReservationDoesNotExists),
+            Some(lease) => {
+                let id = lease.id();
+                let amount = lease.amount();
+
+                lease.consume(tag.as_deref());
+
+                ContractResponse::PoolReservationTaken { pool, id, amount }
             }
+        };
+
+        self.emit_events(None);
+
+        response
+    }
+
+    // Command to create up to "count" gas reservations in one message, while the
+    // message has gas for them and the program is under the max reservations of
+    // the runtime (only admins and allowed accounts)
+    pub fn new_gas_reservations(&mut self, count: u32, gas_amount: u64, blocks: u32) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.can_manage_reservations(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAllowedAccounts);
+        }
 
-            Ok(())
-        });
+        let response = match state.create_reservations(count, gas_amount, blocks) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(report) => ContractResponse::ReservationsCreated(report)
+        };
 
-        ContractResponse::ReservationCreated
+        self.emit_events(None);
+
+        response
     }
 
-    // Command to remove expired gas reservation
+    // Command to remove expired gas reservation of all the pools (only admins
+    // and allowed accounts)
     pub fn remove_expired_gas_reservation(&mut self) -> ContractResponse {
-        GasReservationState::state_mut().remove_expired_gas_reservations();
-        ContractResponse::ExpiredGasReservationDeleted
+        let state = GasReservationState::state_mut();
+
+        if !state.can_manage_reservations(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAllowedAccounts);
+        }
+
+        let reclaimed_gas = state.remove_expired_gas_reservations();
+
+        self.emit_events(None);
+
+        ContractResponse::ExpiredGasReservationDeleted { reclaimed_gas }
+    }
+
+    // Command to cancel a gas reservation by its id, the gas is unreserved
+    // (only admins and allowed accounts)
+    pub fn cancel_gas_reservation(&mut self, id: u64) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.can_manage_reservations(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAllowedAccounts);
+        }
+
+        let response = match state.cancel_reservation(id) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(reclaimed_gas) => ContractResponse::GasReservationCancelled { id, reclaimed_gas }
+        };
+
+        self.emit_events(None);
+
+        response
+    }
+
+    // Command to set the refill policy of a pool (only admins)
+    pub fn set_refill_policy(&mut self, pool: PoolName, policy: RefillPolicy) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.is_admin(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAdmins);
+        }
+
+        match state.set_refill_policy(pool.clone(), policy) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(_) => ContractResponse::RefillPolicySet(pool)
+        }
+    }
+
+    // Command to remove the refill policy of a pool (only admins)
+    pub fn remove_refill_policy(&mut self, pool: PoolName) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.is_admin(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAdmins);
+        }
+
+        match state.remove_refill_policy(&pool) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(_) => ContractResponse::RefillPolicyRemoved(pool)
+        }
+    }
+
+    // Command to set the gas kept for the rest of the message when the pools are
+    // refilled and when a batch of reservations is created (only admins)
+    pub fn set_refill_gas_margin(&mut self, margin: u64) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.is_admin(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAdmins);
+        }
+
+        state.set_refill_gas_margin(margin);
+
+        ContractResponse::RefillGasMarginSet(margin)
+    }
+
+    // Command to set the active reservations of a pool under which the "LowWatermark"
+    // event is emitted, 0 removes the low watermark (only admins)
+    pub fn set_low_watermark(&mut self, pool: PoolName, threshold: u32) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.is_admin(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAdmins);
+        }
+
+        match state.set_low_watermark(pool.clone(), threshold) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(_) => ContractResponse::LowWatermarkSet { pool, threshold }
+        }
+    }
+
+    // Command to set the capacity of the consumption log (last consumed
+    // reservations), 0 disables the log (only admins)
+    pub fn set_consumption_log_capacity(&mut self, capacity: u32) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.is_admin(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAdmins);
+        }
+
+        match state.set_consumption_log_capacity(capacity) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(_) => ContractResponse::ConsumptionLogCapacitySet(capacity)
+        }
+    }
+
+    // Command to refill the pools with a refill policy, with the gas of the
+    // message (only admins and allowed accounts)
+    pub fn refill(&mut self) -> ContractResponse {
+        if !GasReservationState::state_ref().can_manage_reservations(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAllowedAccounts);
+        }
+
+        let report = GasReservationState::maintain();
+
+        self.emit_events(None);
+
+        ContractResponse::Refilled(report)
+    }
+
+    // Command to schedule a call to a service of other program after "delay_blocks"
+    // blocks, using a reservation of the scheduler pool. The value is sent from
+    // the balance of the program (only admins and allowed accounts)
+    pub fn schedule_call(
+        &mut self,
+        target: ActorId,
+        service: String,
+        method: String,
+        payload: Vec<u8>,
+        delay_blocks: u32,
+        value: u128
+    ) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.can_manage_reservations(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAllowedAccounts);
+        }
+
+        let call = ScheduledCall { target, service, method, payload, value };
+
+        match state.schedule_call(call, delay_blocks) {
+            Err(e) => {
+                self.emit_events(None);
+
+                ContractResponse::Error(e)
+            },
+            Ok(job) => {
+                self.emit_events(Some(GasReservationEvent::JobScheduled {
+                    id: job.id,
+                    target,
+                    dispatch_at: job.dispatch_at
+                }));
+
+                ContractResponse::JobScheduled(job.id)
+            }
+        }
+    }
+
+    // Command to cancel a scheduled job, the call will not be sent (only admins
+    // and allowed accounts)
+    pub fn cancel_job(&mut self, id: JobId) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.can_manage_reservations(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAllowedAccounts);
+        }
+
+        match state.cancel_job(id) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(_) => {
+                self.emit_events(Some(GasReservationEvent::JobCancelled(id)));
+
+                ContractResponse::JobCancelled(id)
+            }
+        }
+    }
+
+    // Command that receives the wake-up message of a job and sends its call, the
+    // nonce must be the one of the wake-up message of the job (only the program itself)
+    pub fn dispatch_job(&mut self, id: JobId, nonce: u64) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if msg::source() != exec::program_id() {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForProgram);
+        }
+
+        match state.dispatch_job(id, nonce) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(status) => {
+                self.emit_events(Some(GasReservationEvent::JobDispatched { id, status }));
+
+                ContractResponse::JobDispatched { id, status }
+            }
+        }
+    }
+
+    // Command to register a recurring job, each "period_blocks" blocks the handler
+    // (a route of other service of this program) is called and the next run is scheduled
+    // with a reservation of the scheduler pool (only admins and allowed accounts)
+    pub fn register_recurring_job(
+        &mut self,
+        handler_service: String,
+        handler_method: String,
+        payload: Vec<u8>,
+        period_blocks: u32
+    ) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.can_manage_reservations(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAllowedAccounts);
+        }
+
+        match state.register_recurring_job(handler_service, handler_method, payload, period_blocks) {
+            Err(e) => {
+                self.emit_events(None);
+
+                ContractResponse::Error(e)
+            },
+            Ok(job) => {
+                self.emit_events(Some(GasReservationEvent::RecurringJobRegistered {
+                    id: job.id,
+                    period_blocks,
+                    next_run: job.next_run.unwrap_or_default()
+                }));
+
+                ContractResponse::RecurringJobRegistered(job.id)
+            }
+        }
+    }
+
+    // Command that receives the wake-up message of a recurring job, calls the
+    // handler and schedules the next run, the nonce must be the one of the
+    // wake-up message of the next run (only the program itself)
+    pub fn run_recurring_job(&mut self, id: JobId, nonce: u64) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if msg::source() != exec::program_id() {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForProgram);
+        }
+
+        let previous_runs = state.recurring_jobs
+            .get(&id)
+            .map(|job| job.runs);
+
+        match state.run_recurring_job(id, nonce) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(job) => {
+                let mut events = Vec::new();
+
+                if previous_runs != Some(job.runs) {
+                    events.push(GasReservationEvent::RecurringJobRun { id, next_run: job.next_run });
+                }
+
+                if let RecurringJobStatus::Stopped(reason) = job.status {
+                    events.push(GasReservationEvent::RecurringJobStopped { id, reason });
+                }
+
+                self.emit_events(events);
+
+                ContractResponse::RecurringJobStatus { id, status: job.status }
+            }
+        }
+    }
+
+    // Command to add an admin (only admins)
+    pub fn add_admin(&mut self, address: ActorId) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.is_admin(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAdmins);
+        }
+
+        match state.add_admin(address) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(_) => ContractResponse::AdminAdded(address)
+        }
+    }
+
+    // Command to remove an admin, the last admin can not be removed (only admins)
+    pub fn remove_admin(&mut self, address: ActorId) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.is_admin(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAdmins);
+        }
+
+        match state.remove_admin(address) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(_) => ContractResponse::AdminRemoved(address)
+        }
+    }
+
+    // Command to allow an account to create and remove gas reservations (only admins)
+    pub fn allow_account(&mut self, address: ActorId) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.is_admin(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAdmins);
+        }
+
+        match state.allow_account(address) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(_) => ContractResponse::AccountAllowed(address)
+        }
+    }
+
+    // Command to remove an account from the allowed accounts (only admins)
+    pub fn disallow_account(&mut self, address: ActorId) -> ContractResponse {
+        let state = GasReservationState::state_mut();
+
+        if !state.is_admin(msg::source()) {
+            return ContractResponse::Error(GasReservationError::ActionOnlyForAdmins);
+        }
+
+        match state.disallow_account(address) {
+            Err(e) => ContractResponse::Error(e),
+            Ok(_) => ContractResponse::AccountDisallowed(address)
+        }
     }
 
     // Get the number of total active gas reservations
@@ -51,6 +458,15 @@ impl GasReservationService {
         ContractResponse::ActiveGasReservations(total)
     }
 
+    // Get the active gas reservations with their amount of gas, expiry block,
+    // label and blocks remaining
+    pub fn active_gas_reservations(&self) -> ContractResponse {
+        let reservations = GasReservationState::state_ref()
+            .active_gas_reservations_info();
+
+        ContractResponse::ActiveGasReservationsData(reservations)
+    }
+
     // Get expired data of gas reservation
     pub fn expired_gas_reservations(&self) -> ContractResponse {
         let reservations = GasReservationState::state_ref()
@@ -58,15 +474,146 @@ impl GasReservationService {
 
         ContractResponse::ExpiredGasReservations(reservations)
     }
+
+    // Get the number of active gas reservations of a pool and their total gas
+    pub fn pool_status(&self, pool: PoolName) -> ContractResponse {
+        let status = GasReservationState::state_ref()
+            .pool_status(&pool);
+
+        ContractResponse::PoolStatus(status)
+    }
+
+    // Get the names of the pools
+    pub fn pools(&self) -> ContractResponse {
+        ContractResponse::Pools(GasReservationState::state_ref().pool_names())
+    }
+
+    // Get the refill policies of the pools
+    pub fn refill_policies(&self) -> ContractResponse {
+        ContractResponse::RefillPolicies(GasReservationState::state_ref().refill_policies())
+    }
+
+    // Get a job of the scheduler that is waiting for its wake-up message or
+    // that finished recently, with its status
+    pub fn job(&self, id: JobId) -> ContractResponse {
+        match GasReservationState::state_ref().job(id) {
+            None => ContractResponse::Error(GasReservationError::JobDoesNotExists),
+            Some(job) => ContractResponse::Job(job.clone())
+        }
+    }
+
+    // Get the jobs that are waiting to be dispatched
+    pub fn scheduled_jobs(&self) -> ContractResponse {
+        let jobs = GasReservationState::state_ref()
+            .jobs_with_status(JobStatus::Scheduled);
+
+        ContractResponse::Jobs(jobs)
+    }
+
+    // Get the health of the recurring jobs: status, runs, next run and the
+    // reservations left in the scheduler pool for them
+    pub fn job_health(&self) -> ContractResponse {
+        ContractResponse::JobHealth(GasReservationState::state_ref().job_health())
+    }
+
+    // Get the gas kept for the rest of the message when the pools are refilled
+    pub fn refill_gas_margin(&self) -> ContractResponse {
+        ContractResponse::RefillGasMargin(GasReservationState::state_ref().refill_gas_margin)
+    }
+
+    // Get the low watermarks of the pools
+    pub fn low_watermarks(&self) -> ContractResponse {
+        ContractResponse::LowWatermarks(GasReservationState::state_ref().low_watermarks())
+    }
+
+    // Get a page of the consumption log, from the oldest record
+    pub fn consumption_log(&self, offset: u32, limit: u32) -> ContractResponse {
+        let page = GasReservationState::state_ref()
+            .consumption_log_page(offset, limit);
+
+        ContractResponse::ConsumptionLog(page)
+    }
+
+    // Get the admins of the service
+    pub fn admins(&self) -> ContractResponse {
+        ContractResponse::Admins(GasReservationState::state_ref().admins.clone())
+    }
+
+    // Get the accounts that can create and remove gas reservations (besides admins)
+    pub fn allowed_accounts(&self) -> ContractResponse {
+        ContractResponse::AllowedAccounts(GasReservationState::state_ref().allowed_accounts.clone())
+    }
+
+    // Emits the events of the reservations queued by the state (also the ones of
+    // the reservations taken by an extending service since the last command) and
+    // then the events of the command. Panics if an event can not be sent, so the
+    // changes of the message are reverted instead of being kept without their event
+    fn emit_events(&mut self, events: impl IntoIterator<Item = GasReservationEvent>) {
+        let pending_events = GasReservationState::state_mut().take_pending_events();
+
+        for event in pending_events.into_iter().chain(events) {
+            self.emit_event(event)
+                .expect("Error while emitting the event");
+        }
+    }
 }
 
-#[derive(Encode, Decode, TypeInfo)]
+#[derive(Encode, Decode, TypeInfo, PartialEq, Eq, Debug)]
 #[codec(crate = sails_rs::scale_codec)]
 #[scale_info(crate = sails_rs::scale_info)]
 pub enum ContractResponse {
     ReservationCreated,
-    ExpiredGasReservationDeleted,
+    PoolReservationCreated(PoolName),
+    PoolReservationTaken {
+        pool: PoolName,
+        id: u64,
+        amount: u64
+    },
+    ReservationsCreated(BatchReport),
+    ExpiredGasReservationDeleted {
+        reclaimed_gas: u64
+    },
+    GasReservationCancelled {
+        id: u64,
+        reclaimed_gas: u64
+    },
+    RefillPolicySet(PoolName),
+    RefillPolicyRemoved(PoolName),
+    Refilled(RefillReport),
+    RefillGasMarginSet(u64),
+    ConsumptionLogCapacitySet(u32),
+    LowWatermarkSet {
+        pool: PoolName,
+        threshold: u32
+    },
+    JobScheduled(JobId),
+    JobCancelled(JobId),
+    JobDispatched {
+        id: JobId,
+        status: JobStatus
+    },
+    RecurringJobRegistered(JobId),
+    RecurringJobStatus {
+        id: JobId,
+        status: RecurringJobStatus
+    },
+    AdminAdded(ActorId),
+    AdminRemoved(ActorId),
+    AccountAllowed(ActorId),
+    AccountDisallowed(ActorId),
     ActiveGasReservations(u32),
-    ExpiredGasReservations(Vec<GasReservationData>)
+    ActiveGasReservationsData(Vec<ActiveGasReservation>),
+    ExpiredGasReservations(Vec<GasReservationData>),
+    PoolStatus(PoolStatus),
+    Pools(Vec<PoolName>),
+    RefillPolicies(Vec<(PoolName, RefillPolicy)>),
+    RefillGasMargin(u64),
+    LowWatermarks(Vec<(PoolName, u32)>),
+    ConsumptionLog(ConsumptionLogPage),
+    Job(Job),
+    Jobs(Vec<Job>),
+    JobHealth(Vec<JobHealth>),
+    Admins(Vec<ActorId>),
+    AllowedAccounts(Vec<ActorId>),
+    Error(GasReservationError)
 }
-
@@ -0,0 +1,172 @@
+use sails_rs::{
+    prelude::*,
+    collections::VecDeque
+};
+use crate::service_enums::GasReservationError;
+use super::{
+    Blocks,
+    PoolName,
+    GasReservationState
+};
+
+// Max records of the consumption log
+pub const MAX_CONSUMPTION_LOG_CAPACITY: u32 = 1_000;
+// Max records returned by a page of the consumption log
+pub const MAX_CONSUMPTION_LOG_PAGE: u32 = 100;
+// Max characters of the consumer tags (longer tags are cut)
+pub const MAX_CONSUMER_TAG_LENGTH: usize = 32;
+
+// # Record of a consumed reservation
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct ConsumptionRecord {
+    // Position of the record in all the records logged
+    pub seq: u64,
+    // Internal id of the reservation
+    pub reservation_id: u64,
+    pub pool: PoolName,
+    // Tag of the consumer (set with "take_reservation")
+    pub tag: Option<String>,
+    pub block: Blocks,
+    pub amount: u64
+}
+
+// # Page of the consumption log (query)
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug, Default)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct ConsumptionLogPage {
+    pub records: Vec<ConsumptionRecord>,
+    // Records in the log
+    pub len: u32,
+    // Records logged since the log was enabled (the oldest ones are dropped)
+    pub total_logged: u64
+}
+
+// # Ring buffer with the last consumed reservations
+#[derive(Default, Clone)]
+pub struct ConsumptionLog {
+    pub capacity: u32,
+    pub records: VecDeque<ConsumptionRecord>,
+    pub total_logged: u64
+}
+
+impl ConsumptionLog {
+    pub fn new(capacity: u32) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    // Adds a record, the oldest record is dropped if the log is full
+    pub fn push(&mut self, reservation_id: u64, pool: &str, tag: Option<&str>, block: Blocks, amount: u64) {
+        if self.records.len() >= self.capacity as usize {
+            self.records.pop_front();
+        }
+
+        self.records.push_back(ConsumptionRecord {
+            seq: self.total_logged,
+            reservation_id,
+            pool: pool.to_string(),
+            tag: tag.map(|tag| tag.chars().take(MAX_CONSUMER_TAG_LENGTH).collect()),
+            block,
+            amount
+        });
+
+        self.total_logged = self.total_logged.saturating_add(1);
+    }
+
+    // Changes the capacity, keeping the last records
+    pub fn set_capacity(&mut self, capacity: u32) {
+        while self.records.len() > capacity as usize {
+            self.records.pop_front();
+        }
+
+        self.capacity = capacity;
+    }
+
+    // ### Page of the records, from the oldest one
+    pub fn page(&self, offset: u32, limit: u32) -> ConsumptionLogPage {
+        let limit = limit.min(MAX_CONSUMPTION_LOG_PAGE);
+
+        ConsumptionLogPage {
+            records: self.records
+                .iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .cloned()
+                .collect(),
+            len: self.records.len() as u32,
+            total_logged: self.total_logged
+        }
+    }
+}
+
+// ## Methods of the consumption log
+impl GasReservationState {
+    // ### Sets the capacity of the consumption log
+    // 0 disables the log (its records are removed)
+    pub fn set_consumption_log_capacity(&mut self, capacity: u32) -> Result<(), GasReservationError> {
+        if capacity > MAX_CONSUMPTION_LOG_CAPACITY {
+            return Err(GasReservationError::LimitExceeded);
+        }
+
+        if capacity == 0 {
+            self.consumption_log = None;
+        } else if let Some(log) = self.consumption_log.as_mut() {
+            log.set_capacity(capacity);
+        } else {
+            self.consumption_log = Some(ConsumptionLog::new(capacity));
+        }
+
+        Ok(())
+    }
+
+    pub fn consumption_log_page(&self, offset: u32, limit: u32) -> ConsumptionLogPage {
+        self.consumption_log
+            .as_ref()
+            .map(|log| log.page(offset, limit))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_keeps_the_last_records() {
+        let mut log = ConsumptionLog::new(3);
+
+        for id in 0..5u64 {
+            log.push(id, "default", Some("rewards"), id as u32, 1_000);
+        }
+
+        let page = log.page(0, 10);
+        assert_eq!(page.len, 3);
+        assert_eq!(page.total_logged, 5);
+
+        let ids: Vec<u64> = page.records.iter().map(|record| record.reservation_id).collect();
+        assert_eq!(ids, vec![2, 3, 4]);
+
+        let page = log.page(1, 1);
+        assert_eq!(page.records.len(), 1);
+        assert_eq!(page.records[0].seq, 3);
+        assert_eq!(page.records[0].tag, Some("rewards".to_string()));
+
+        // The last records are kept
+        log.set_capacity(1);
+        assert_eq!(log.page(0, 10).records[0].reservation_id, 4);
+    }
+
+    #[test]
+    fn long_tags_are_cut() {
+        let mut log = ConsumptionLog::new(1);
+
+        log.push(0, "default", Some(&"a".repeat(40)), 1, 1_000);
+
+        assert_eq!(log.page(0, 1).records[0].tag, Some("a".repeat(MAX_CONSUMER_TAG_LENGTH)));
+    }
+}
@@ -0,0 +1,156 @@
+use sails_rs::{
+    prelude::*,
+    gstd::exec
+};
+use crate::service_enums::{
+    GasReservationError,
+    RuntimeErrorKind
+};
+use super::{
+    Blocks,
+    GasReservationState,
+    DEFAULT_POOL,
+    MAX_POOL_NAME_LENGTH
+};
+
+// Max reservations of a program in the runtime (256 in the Vara runtime and gtest)
+pub const MAX_RESERVATIONS_PER_PROGRAM: u32 = 256;
+
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum BatchStopReason {
+    // All the reservations were created
+    Completed,
+    // The message has not enough gas for the next reservation
+    NotEnoughGas,
+    // The program has the max reservations of the runtime
+    ReservationsLimitReached,
+    // No more ids for reservations
+    IdOverflow,
+    // Other error of the runtime
+    ReservationFailed(RuntimeErrorKind),
+    // Other error of the service
+    Error(GasReservationError)
+}
+
+// # Result of a batch of reservations
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct BatchReport {
+    // Reservations created
+    pub created: u32,
+    pub stop_reason: BatchStopReason
+}
+
+// ## Methods to create several reservations in one message
+impl GasReservationState {
+    // ### Creates up to "count" reservations in the default pool
+    pub fn create_reservations(&mut self, count: u32, amount: u64, blocks: Blocks) -> Result<BatchReport, GasReservationError> {
+        self.create_pool_reservations(DEFAULT_POOL, count, amount, blocks)
+    }
+
+    // ### Creates up to "count" reservations in the pool
+    // The reservations are created while the message has gas for them (keeping
+    // "refill_gas_margin" for the rest of the message) and the reservations of
+    // the service are under the max reservations of the runtime (reservations of
+    // the program outside of the service are not counted). The expired
+    // reservations of all the pools are removed first. The arguments are checked
+    // before creating any reservation, the reservations created are kept if the
+    // batch stops early
+    pub fn create_pool_reservations(
+        &mut self,
+        pool: &str,
+        count: u32,
+        amount: u64,
+        blocks: Blocks
+    ) -> Result<BatchReport, GasReservationError> {
+        if amount == 0 {
+            return Err(GasReservationError::ZeroGas);
+        }
+
+        if blocks == 0 {
+            return Err(GasReservationError::ZeroBlocks);
+        }
+
+        if pool.len() > MAX_POOL_NAME_LENGTH {
+            return Err(GasReservationError::LimitExceeded);
+        }
+
+        self.remove_expired_gas_reservations();
+
+        let mut reservations = self.tracked_reservations();
+        let mut report = BatchReport {
+            created: 0,
+            stop_reason: BatchStopReason::Completed
+        };
+
+        while report.created < count {
+            if reservations >= MAX_RESERVATIONS_PER_PROGRAM {
+                report.stop_reason = BatchStopReason::ReservationsLimitReached;
+                break;
+            }
+
+            if exec::gas_available() < amount.saturating_add(self.refill_gas_margin) {
+                report.stop_reason = BatchStopReason::NotEnoughGas;
+                break;
+            }
+
+            match self.create_pool_reservation(pool, amount, blocks, None) {
+                Ok(_) => {
+                    report.created += 1;
+                    reservations += 1;
+                },
+                Err(e) => {
+                    report.stop_reason = BatchStopReason::from(e);
+                    break;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    // Reservations of all the pools (the ones of the program in the runtime that
+    // the service knows of)
+    pub(super) fn tracked_reservations(&self) -> u32 {
+        self.pools
+            .values()
+            .map(|pool| pool.gas_reservations_data.len() as u32)
+            .sum()
+    }
+}
+
+impl From<GasReservationError> for BatchStopReason {
+    fn from(error: GasReservationError) -> Self {
+        match error {
+            GasReservationError::IdOverflow => Self::IdOverflow,
+            GasReservationError::ReservationFailed(RuntimeErrorKind::NotEnoughGas) => Self::NotEnoughGas,
+            GasReservationError::ReservationFailed(RuntimeErrorKind::ReservationsLimitReached) => Self::ReservationsLimitReached,
+            GasReservationError::ReservationFailed(kind) => Self::ReservationFailed(kind),
+            // The arguments are checked before the batch, and the other errors
+            // are not returned by the reservations, they are kept as they are
+            GasReservationError::ActionOnlyForAdmins
+            | GasReservationError::ActionOnlyForAllowedAccounts
+            | GasReservationError::ActionOnlyForProgram
+            | GasReservationError::AdminAlreadyExists
+            | GasReservationError::AdminDoesNotExists
+            | GasReservationError::CanNotRemoveLastAdmin
+            | GasReservationError::AccountAlreadyAllowed
+            | GasReservationError::AccountIsNotAllowed
+            | GasReservationError::ReservationDoesNotExists
+            | GasReservationError::InvalidRefillPolicy
+            | GasReservationError::RefillPolicyDoesNotExists
+            | GasReservationError::NoReservationForJob
+            | GasReservationError::JobDoesNotExists
+            | GasReservationError::JobIsNotScheduled
+            | GasReservationError::ZeroGas
+            | GasReservationError::ZeroBlocks
+            | GasReservationError::LimitExceeded
+            | GasReservationError::JobSchedulingFailed(_)
+            | GasReservationError::InvalidJobHandler
+            | GasReservationError::InvalidWakeUp => Self::Error(error)
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use sails_rs::prelude::*;
+use crate::service_enums::{
+    GasReservationError,
+    GasReservationEvent
+};
+use super::{
+    Blocks,
+    PoolName,
+    GasReservationState,
+    MAX_POOL_NAME_LENGTH
+};
+
+// ## Events of the reservations and low watermarks of the pools
+impl GasReservationState {
+    // ### Queues an event of the reservations
+    // The events of the reservations are also queued when they are taken outside
+    // of the service methods (for example, by an extending service). The service
+    // emits the queued events with the sails events of its next command
+    pub fn queue_event(&mut self, event: GasReservationEvent) {
+        self.pending_events.push(event);
+    }
+
+    // Takes the queued events, in the order that they were queued
+    pub fn take_pending_events(&mut self) -> Vec<GasReservationEvent> {
+        core::mem::take(&mut self.pending_events)
+    }
+
+    // Sets the active reservations of the pool under which the "LowWatermark"
+    // event is emitted, 0 removes the low watermark
+    pub fn set_low_watermark(&mut self, pool: PoolName, threshold: u32) -> Result<(), GasReservationError> {
+        if pool.len() > MAX_POOL_NAME_LENGTH {
+            return Err(GasReservationError::LimitExceeded);
+        }
+
+        // The pool is checked again with the new threshold
+        if let Some(pool) = self.pools.get_mut(&pool) {
+            pool.below_low_watermark = false;
+        }
+
+        if threshold == 0 {
+            self.low_watermarks.remove(&pool);
+        } else {
+            self.low_watermarks.insert(pool, threshold);
+        }
+
+        Ok(())
+    }
+
+    // Low watermarks sorted by pool name
+    pub fn low_watermarks(&self) -> Vec<(PoolName, u32)> {
+        let mut low_watermarks: Vec<(PoolName, u32)> = self.low_watermarks
+            .iter()
+            .map(|(pool, threshold)| (pool.clone(), *threshold))
+            .collect();
+
+        low_watermarks.sort();
+
+        low_watermarks
+    }
+
+    // Queues the "LowWatermark" event when the active reservations of the pool
+    // fall below its low watermark (called after removing reservations). The event
+    // is queued once, until the pool is at its low watermark again
+    pub(crate) fn check_low_watermark(&mut self, pool_name: &str, block_height: Blocks) {
+        let Some(threshold) = self.low_watermarks.get(pool_name).copied() else {
+            return;
+        };
+
+        let Some(pool) = self.pools.get_mut(pool_name) else {
+            return;
+        };
+
+        let active_reservations = pool.status(block_height).active_reservations;
+        let below = active_reservations < threshold;
+        let crossed = below && !pool.below_low_watermark;
+
+        pool.below_low_watermark = below;
+
+        if crossed {
+            self.queue_event(GasReservationEvent::LowWatermark {
+                pool: pool_name.to_string(),
+                active_reservations,
+                threshold
+            });
+        }
+    }
+
+    // Clears the "below" flag of the pool if its active reservations are back at
+    // its low watermark (called after adding reservations)
+    pub(crate) fn reset_low_watermark(&mut self, pool_name: &str, block_height: Blocks) {
+        let Some(threshold) = self.low_watermarks.get(pool_name).copied() else {
+            return;
+        };
+
+        if let Some(pool) = self.pools.get_mut(pool_name) {
+            if pool.status(block_height).active_reservations >= threshold {
+                pool.below_low_watermark = false;
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use sails_rs::{
+    prelude::*,
+    gstd::exec
+};
+use gstd::ReservationId;
+use super::{
+    Blocks,
+    PoolName,
+    GasReservationData,
+    GasReservationPool,
+    GasReservationState,
+    DEFAULT_POOL
+};
+
+// # Lease of a reservation
+// The reservation is out of the queue while the lease lives, "consume" uses it
+// for good. The id of the reservation is only handed out by "consume" and
+// "consume_if", so it can not be used while the lease puts it back. If the
+// lease is dropped without "consume" (for example, an error in the code path
+// that needed the gas), the reservation is put back at its place in the queue
+// (by creation order), so it is not wasted
+#[must_use = "the reservation is put back in the queue if the lease is not consumed"]
+pub struct ReservationLease {
+    pool: PoolName,
+    // None once the reservation is consumed
+    data: Option<GasReservationData>
+}
+
+impl ReservationLease {
+    // Internal id of the reservation
+    pub fn id(&self) -> u64 {
+        self.data().id
+    }
+
+    pub fn amount(&self) -> u64 {
+        self.data().amount
+    }
+
+    pub fn expires_at(&self) -> Blocks {
+        self.data().expires_at
+    }
+
+    pub fn pool(&self) -> &str {
+        &self.pool
+    }
+
+    // ### Uses the reservation only if "f" succeeds
+    // "f" receives the id of the reservation (for example, to send a message
+    // from it). If it returns an error, the reservation is put back in the queue
+    pub fn consume_if<T, E>(self, tag: Option<&str>, f: impl FnOnce(ReservationId) -> Result<T, E>) -> Result<T, E> {
+        let result = f(self.data().reservation_id)?;
+
+        self.consume(tag);
+
+        Ok(result)
+    }
+
+    // ### Uses the reservation
+    // The reservation is not put back in the queue, it is stored in the
+    // consumption log with the tag of the consumer (if the log is enabled)
+    // and the "ReservationConsumed" event is emitted
+    pub fn consume(mut self, tag: Option<&str>) -> ReservationId {
+        let data = self.data
+            .take()
+            .expect("The lease holds the reservation until it is consumed");
+
+        GasReservationState::state_mut().record_consumption(&self.pool, tag, &data, exec::block_height());
+
+        data.reservation_id
+    }
+
+    fn data(&self) -> &GasReservationData {
+        self.data
+            .as_ref()
+            .expect("The lease holds the reservation until it is consumed")
+    }
+}
+
+impl Drop for ReservationLease {
+    fn drop(&mut self) {
+        if let Some(data) = self.data.take() {
+            GasReservationState::state_mut()
+                .pools
+                .entry(self.pool.clone())
+                .or_default()
+                .restore(data);
+        }
+    }
+}
+
+// ## Methods to lease reservations
+impl GasReservationState {
+    // ### Leases the oldest active reservation of the default pool
+    pub fn lease_reservation() -> Option<ReservationLease> {
+        Self::lease_pool_reservation(DEFAULT_POOL)
+    }
+
+    // ### Leases the oldest active reservation of the pool
+    // The expired reservations of the pool are removed first
+    pub fn lease_pool_reservation(pool: &str) -> Option<ReservationLease> {
+        Self::lease_pool_reservation_with(pool, |pool, block_height| pool.take(block_height))
+    }
+
+    // ### Leases the smallest reservation of the pool with at least "min_gas" that
+    // can still be used during "min_remaining_blocks" blocks (see "get_reservation_for")
+    pub fn lease_pool_reservation_for(pool: &str, min_gas: u64, min_remaining_blocks: Blocks) -> Option<ReservationLease> {
+        Self::lease_pool_reservation_with(pool, |pool, block_height| {
+            pool.take_best_fit(min_gas, min_remaining_blocks, block_height)
+        })
+    }
+
+    fn lease_pool_reservation_with<F>(pool: &str, lease: F) -> Option<ReservationLease>
+    where
+        F: FnOnce(&mut GasReservationPool, Blocks) -> Option<GasReservationData>
+    {
+        let state = Self::state_mut();
+        let block_height = exec::block_height();
+
+        state.remove_expired_from_pool(pool, block_height);
+
+        let data = lease(state.pools.get_mut(pool)?, block_height)?;
+
+        Some(ReservationLease {
+            pool: pool.to_string(),
+            data: Some(data)
+        })
+    }
+}
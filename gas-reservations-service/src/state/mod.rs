@@ -1,146 +1,516 @@
-use sails_rs::{
-    prelude::*,
-    collections::VecDeque,
-    gstd::exec
-};
-use gstd::{ReservationId, ReservationIdExt};
-pub type Blocks = u32;
-
-static mut GAS_RESERVATION_STATE: Option<GasReservationState> = None;
-
-#[derive(Default)]
-pub struct GasReservationState {
-    pub gas_reservations_data: VecDeque<GasReservationData>,
-    pub current_reservation_id: u64
-}
-
-impl GasReservationState {
-    pub fn init_state() {
-        unsafe {
-            GAS_RESERVATION_STATE = Some(GasReservationState::default());
-        };
-    }
-
-    pub fn state_mut() -> &'static mut Self {
-        unsafe { 
-            GAS_RESERVATION_STATE
-                .as_mut()
-                .expect("State is not initialized")
-        }
-    }
-
-    pub fn state_ref() -> &'static Self {
-        unsafe { 
-            GAS_RESERVATION_STATE
-                .as_ref()
-                .expect("State is not initialized")
-        }
-    }
-}
-
-impl GasReservationState {
-    pub fn get_reservation_id() -> Option<ReservationId> {
-	let state = Self::state_mut();
-        let block_height = exec::block_height();
-
-        loop {
-            let data = state.gas_reservations_data.pop_front();
-
-            let Some(GasReservationData { reservation_id, created_at, duration, .. }) = data else {
-                return None;
-            };
-
-            let expiration = created_at + duration;
-
-            if block_height < expiration {
-                return Some(reservation_id);
-            }
-        };
-    }
-
-    pub fn create_reservation(&mut self, amount: u64, blocks: Blocks) -> Result<ReservationId, String> {
-        if amount == 0 {
-            return Err(String::from("Gas to store can not be 0"));
-        }
-
-        if blocks == 0 {
-            return Err(String::from("Blocks can not be 0"));
-        }
-            
-        let gas_reservation_data_id = self.current_reservation_id
-            .checked_add(1)
-            .ok_or(String::from("reservation ids overflow"))?;
-
-        let reservation_id: ReservationId = ReservationId::reserve(amount, blocks)
-            .map_err(|e| e.to_string())?;
-
-        let block_height = exec::block_height();
-        let reservation_data = GasReservationData::new(
-            self.current_reservation_id,
-            reservation_id, 
-            block_height, 
-            blocks
-        );
-
-        self.gas_reservations_data.push_back(reservation_data);
-        self.current_reservation_id = gas_reservation_data_id;
-
-        Ok(reservation_id)
-    }
-
-    pub fn remove_expired_gas_reservations(&mut self) {
-        let block_height = exec::block_height();
-        
-        self.gas_reservations_data.retain(|data| (data.created_at + data.duration) < block_height);
-    }
-
-    pub fn active_gas_reservations(&self) -> u32 {
-        let mut total = 0;
-        let block_height = exec::block_height();
-
-        self.gas_reservations_data
-            .iter()
-            .for_each(|data| {
-                if (data.created_at + data.duration) > block_height {
-                    total += 1;
-                }
-            });
-
-        total
-    }
-
-    pub fn get_expired_gas_reservations(&self) -> Vec<GasReservationData> {
-        let block_height = exec::block_height();
-        self.gas_reservations_data
-            .iter()
-            .filter(|&data| (data.created_at + data.duration) < block_height)
-            .map(|data| data.clone())
-            .collect()
-    }
-}
-
-#[derive(Encode, Decode, TypeInfo, Clone)]
-#[codec(crate = sails_rs::scale_codec)]
-#[scale_info(crate = sails_rs::scale_info)]
-pub struct GasReservationData {
-    id: u64,
-    reservation_id: ReservationId,
-    created_at: Blocks,
-    duration: Blocks
-}
-
-impl GasReservationData { 
-    pub fn new(
-        id: u64,
-        reservation_id: ReservationId,
-        created_at: Blocks,
-        duration: Blocks
-    ) -> Self {
-        Self {
-            id,
-            reservation_id,
-            created_at,
-            duration
-        }
-    }
-}
+use sails_rs::{
+    prelude::*,
+    collections::{HashMap, VecDeque},
+    gstd::{exec, msg}
+};
+use gstd::{ReservationId, ReservationIdExt};
+use crate::service_enums::{
+    GasReservationError,
+    GasReservationEvent
+};
+
+pub mod pool;
+pub mod refill;
+pub mod scheduler;
+pub mod recurring;
+pub mod events;
+pub mod audit;
+pub mod lease;
+pub mod batch;
+
+pub use pool::{
+    GasReservationPool,
+    PoolStatus
+};
+pub use refill::{
+    RefillFailure,
+    RefillPolicy,
+    RefillReport
+};
+pub use scheduler::{
+    Job,
+    JobId,
+    JobStatus,
+    ScheduledCall
+};
+pub use audit::{
+    ConsumptionLog,
+    ConsumptionLogPage,
+    ConsumptionRecord
+};
+pub use lease::ReservationLease;
+pub use batch::{
+    BatchReport,
+    BatchStopReason,
+    MAX_RESERVATIONS_PER_PROGRAM
+};
+pub use recurring::{
+    JobHealth,
+    JobStopReason,
+    RecurringJob,
+    RecurringJobStatus
+};
+
+pub type Blocks = u32;
+pub type PoolName = String;
+
+// Pool of the reservations created and taken without a pool name
+pub const DEFAULT_POOL: &str = "default";
+// Max length (bytes) of the pool names and the labels of the reservations
+pub const MAX_POOL_NAME_LENGTH: usize = 32;
+pub const MAX_LABEL_LENGTH: usize = 64;
+
+static mut GAS_RESERVATION_STATE: Option<GasReservationState> = None;
+
+#[derive(Default)]
+pub struct GasReservationState {
+    // Reservations of each pool, one purpose can not take the gas of another
+    pub pools: HashMap<PoolName, GasReservationPool>,
+    // Refill policy of the pools that are refilled with "maintain"
+    pub refill_policies: HashMap<PoolName, RefillPolicy>,
+    // Gas kept for the rest of the message when the pools are refilled
+    // (also by the batches of reservations)
+    pub refill_gas_margin: u64,
+    // Active reservations of each pool under which the "LowWatermark" event is emitted
+    pub low_watermarks: HashMap<PoolName, u32>,
+    // Last consumed reservations (disabled if None)
+    pub consumption_log: Option<ConsumptionLog>,
+    // Jobs of the scheduler
+    pub jobs: HashMap<JobId, Job>,
+    // Last jobs whose wake-up message arrived, with their final status
+    pub finished_jobs: VecDeque<Job>,
+    // Recurring jobs of the scheduler (same ids as the jobs)
+    pub recurring_jobs: HashMap<JobId, RecurringJob>,
+    // Last recurring jobs that were cancelled or stopped
+    pub finished_recurring_jobs: VecDeque<RecurringJob>,
+    pub current_job_id: JobId,
+    // Nonce of the next wake-up message, a wake-up message is only accepted with
+    // the nonce that its job expects
+    pub wake_up_nonce: u64,
+    // Events of the reservations that the service has not emitted yet
+    pub pending_events: Vec<GasReservationEvent>,
+    // Route of the service, used by the wake-up messages of the jobs (only
+    // changed with "set_service_route")
+    pub(crate) service_route: String,
+    pub current_reservation_id: u64,
+    // Admins of the service (manage admins and allowed accounts)
+    pub admins: Vec<ActorId>,
+    // Accounts that can create and remove gas reservations (besides admins)
+    pub allowed_accounts: Vec<ActorId>
+}
+
+impl GasReservationState {
+    // The account that deploys the program is the first admin
+    pub fn init_state() {
+        let mut state = GasReservationState {
+            service_route: String::from(scheduler::DEFAULT_SERVICE_ROUTE),
+            refill_gas_margin: refill::DEFAULT_REFILL_GAS_MARGIN,
+            ..Default::default()
+        };
+        state.admins.push(msg::source());
+
+        unsafe {
+            GAS_RESERVATION_STATE = Some(state);
+        };
+    }
+
+    pub fn state_mut() -> &'static mut Self {
+        unsafe { 
+            GAS_RESERVATION_STATE
+                .as_mut()
+                .expect("State is not initialized")
+        }
+    }
+
+    pub fn state_ref() -> &'static Self {
+        unsafe { 
+            GAS_RESERVATION_STATE
+                .as_ref()
+                .expect("State is not initialized")
+        }
+    }
+}
+
+// Methods to manage the admins and allowed accounts
+impl GasReservationState {
+    pub fn is_admin(&self, address: ActorId) -> bool {
+        self.admins.contains(&address)
+    }
+
+    // Admins and allowed accounts can manage the gas reservations
+    pub fn can_manage_reservations(&self, address: ActorId) -> bool {
+        self.is_admin(address) || self.allowed_accounts.contains(&address)
+    }
+
+    pub fn add_admin(&mut self, address: ActorId) -> Result<(), GasReservationError> {
+        if self.is_admin(address) {
+            return Err(GasReservationError::AdminAlreadyExists);
+        }
+
+        self.admins.push(address);
+
+        Ok(())
+    }
+
+    pub fn remove_admin(&mut self, address: ActorId) -> Result<(), GasReservationError> {
+        if !self.is_admin(address) {
+            return Err(GasReservationError::AdminDoesNotExists);
+        }
+
+        // The service can not be left without admins
+        if self.admins.len() == 1 {
+            return Err(GasReservationError::CanNotRemoveLastAdmin);
+        }
+
+        self.admins.retain(|admin| *admin != address);
+
+        Ok(())
+    }
+
+    pub fn allow_account(&mut self, address: ActorId) -> Result<(), GasReservationError> {
+        if self.allowed_accounts.contains(&address) {
+            return Err(GasReservationError::AccountAlreadyAllowed);
+        }
+
+        self.allowed_accounts.push(address);
+
+        Ok(())
+    }
+
+    pub fn disallow_account(&mut self, address: ActorId) -> Result<(), GasReservationError> {
+        if !self.allowed_accounts.contains(&address) {
+            return Err(GasReservationError::AccountIsNotAllowed);
+        }
+
+        self.allowed_accounts.retain(|account| *account != address);
+
+        Ok(())
+    }
+}
+
+impl GasReservationState {
+    pub fn get_reservation_id() -> Option<ReservationId> {
+        Self::get_pool_reservation_id(DEFAULT_POOL)
+    }
+
+    // Same as "get_reservation_id", the tag of the consumer is stored in the
+    // consumption log (if it is enabled) to know who used the reservation
+    pub fn take_reservation(tag: &str) -> Option<ReservationId> {
+        Self::state_mut().take_pool_reservation(DEFAULT_POOL, Some(tag), |pool, block_height| pool.take(block_height))
+    }
+
+    // Takes the oldest active reservation of the pool
+    pub fn get_pool_reservation_id(pool: &str) -> Option<ReservationId> {
+        Self::state_mut().take_pool_reservation(pool, None, |pool, block_height| pool.take(block_height))
+    }
+
+    // Takes the smallest reservation with at least "min_gas" that can still be used
+    // during "min_remaining_blocks" blocks (the one that expires first if there are
+    // several with the same amount). The other reservations are kept in the queue
+    pub fn get_reservation_for(min_gas: u64, min_remaining_blocks: Blocks) -> Option<ReservationId> {
+        Self::get_pool_reservation_for(DEFAULT_POOL, min_gas, min_remaining_blocks)
+    }
+
+    // Same as "get_reservation_for", with the reservations of the pool
+    pub fn get_pool_reservation_for(pool: &str, min_gas: u64, min_remaining_blocks: Blocks) -> Option<ReservationId> {
+        Self::state_mut().take_pool_reservation(pool, None, |pool, block_height| {
+            pool.take_best_fit(min_gas, min_remaining_blocks, block_height)
+        })
+    }
+
+    // ### Takes a reservation of the pool with the given function
+    // The expired reservations of the pool are removed first (from the runtime
+    // too), the events of the removed and consumed reservations are queued and
+    // the consumed reservation is stored in the consumption log
+    pub(crate) fn take_pool_reservation<F>(&mut self, pool_name: &str, tag: Option<&str>, take: F) -> Option<ReservationId>
+    where
+        F: FnOnce(&mut GasReservationPool, Blocks) -> Option<GasReservationData>
+    {
+        let block_height = exec::block_height();
+
+        self.remove_expired_from_pool(pool_name, block_height);
+
+        let data = take(self.pools.get_mut(pool_name)?, block_height)?;
+
+        self.record_consumption(pool_name, tag, &data, block_height);
+
+        Some(data.reservation_id)
+    }
+
+    // Stores the consumed reservation in the consumption log and queues its event
+    fn record_consumption(&mut self, pool_name: &str, tag: Option<&str>, data: &GasReservationData, block_height: Blocks) {
+        if let Some(log) = self.consumption_log.as_mut() {
+            log.push(data.id, pool_name, tag, block_height, data.amount);
+        }
+
+        self.queue_event(GasReservationEvent::ReservationConsumed {
+            id: data.id,
+            pool: pool_name.to_string()
+        });
+        self.check_low_watermark(pool_name, block_height);
+    }
+
+    // Removes the expired reservations of the pool, returns the gas reclaimed
+    fn remove_expired_from_pool(&mut self, pool_name: &str, block_height: Blocks) -> u64 {
+        let Some(pool) = self.pools.get_mut(pool_name) else {
+            return 0;
+        };
+
+        let (count, reclaimed_gas) = pool.remove_expired(block_height);
+
+        if count > 0 {
+            self.queue_event(GasReservationEvent::ExpiredReservationsRemoved {
+                pool: pool_name.to_string(),
+                count,
+                reclaimed_gas
+            });
+            self.check_low_watermark(pool_name, block_height);
+        }
+
+        reclaimed_gas
+    }
+
+    pub fn create_reservation(
+        &mut self,
+        amount: u64,
+        blocks: Blocks,
+        label: Option<String>
+    ) -> Result<ReservationId, GasReservationError> {
+        self.create_pool_reservation(DEFAULT_POOL, amount, blocks, label)
+    }
+
+    // Reserves gas in the given pool, the pool is created with its first reservation
+    pub fn create_pool_reservation(
+        &mut self,
+        pool: &str,
+        amount: u64,
+        blocks: Blocks,
+        label: Option<String>
+    ) -> Result<ReservationId, GasReservationError> {
+        if amount == 0 {
+            return Err(GasReservationError::ZeroGas);
+        }
+
+        if blocks == 0 {
+            return Err(GasReservationError::ZeroBlocks);
+        }
+
+        let label_too_long = label
+            .as_ref()
+            .is_some_and(|label| label.len() > MAX_LABEL_LENGTH);
+
+        if pool.len() > MAX_POOL_NAME_LENGTH || label_too_long {
+            return Err(GasReservationError::LimitExceeded);
+        }
+            
+        let gas_reservation_data_id = self.current_reservation_id
+            .checked_add(1)
+            .ok_or(GasReservationError::IdOverflow)?;
+
+        let reservation_id: ReservationId = ReservationId::reserve(amount, blocks)
+            .map_err(|e| GasReservationError::ReservationFailed(e.into()))?;
+
+        let block_height = exec::block_height();
+        let reservation_data = GasReservationData::new(
+            self.current_reservation_id,
+            reservation_id, 
+            amount,
+            block_height, 
+            blocks,
+            label
+        );
+
+        self.queue_event(GasReservationEvent::ReservationCreated {
+            id: reservation_data.id,
+            pool: pool.to_string(),
+            amount,
+            expires_at: reservation_data.expires_at
+        });
+
+        self.pools
+            .entry(pool.to_string())
+            .or_default()
+            .push(reservation_data);
+        self.current_reservation_id = gas_reservation_data_id;
+        self.reset_low_watermark(pool, block_height);
+
+        Ok(reservation_id)
+    }
+
+    // Removes the expired reservations of all the pools from the state and
+    // the runtime. Returns the amount of gas reclaimed
+    pub fn remove_expired_gas_reservations(&mut self) -> u64 {
+        let block_height = exec::block_height();
+
+        self.pool_names()
+            .iter()
+            .map(|pool_name| self.remove_expired_from_pool(pool_name, block_height))
+            .sum()
+    }
+
+    // Removes the reservation with the given id (internal id, unique in all the
+    // pools) from the state and the runtime, returns the amount of gas reclaimed
+    pub fn cancel_reservation(&mut self, id: u64) -> Result<u64, GasReservationError> {
+        let (pool_name, reclaimed_gas) = self.pools
+            .iter_mut()
+            .find_map(|(pool_name, pool)| pool.cancel(id).map(|gas| (pool_name.clone(), gas)))
+            .ok_or(GasReservationError::ReservationDoesNotExists)?;
+
+        self.queue_event(GasReservationEvent::ReservationUnreserved {
+            id,
+            pool: pool_name.clone(),
+            reclaimed_gas
+        });
+        self.check_low_watermark(&pool_name, exec::block_height());
+
+        Ok(reclaimed_gas)
+    }
+
+    // Active reservations of all the pools
+    pub fn active_gas_reservations(&self) -> u32 {
+        let block_height = exec::block_height();
+
+        self.pools
+            .values()
+            .map(|pool| pool.status(block_height).active_reservations)
+            .sum()
+    }
+
+    // Active reservations and their total gas (empty status if the pool does not exist)
+    pub fn pool_status(&self, pool: &str) -> PoolStatus {
+        self.pools
+            .get(pool)
+            .map(|pool| pool.status(exec::block_height()))
+            .unwrap_or_default()
+    }
+
+    // Names of the pools, sorted
+    pub fn pool_names(&self) -> Vec<PoolName> {
+        let mut names: Vec<PoolName> = self.pools
+            .keys()
+            .cloned()
+            .collect();
+
+        names.sort();
+
+        names
+    }
+
+    // Active reservations of all the pools (sorted by pool name) with their pool,
+    // amount, expiry block, label and the blocks that they can still be used
+    pub fn active_gas_reservations_info(&self) -> Vec<ActiveGasReservation> {
+        let block_height = exec::block_height();
+
+        self.pool_names()
+            .iter()
+            .filter_map(|pool_name| self.pools.get(pool_name).map(|pool| pool.active_info(pool_name, block_height)))
+            .flatten()
+            .collect()
+    }
+
+    // Expired reservations of all the pools (sorted by pool name), not removed yet
+    pub fn get_expired_gas_reservations(&self) -> Vec<GasReservationData> {
+        let block_height = exec::block_height();
+
+        self.pool_names()
+            .iter()
+            .filter_map(|pool_name| self.pools.get(pool_name).map(|pool| pool.expired(block_height)))
+            .flatten()
+            .collect()
+    }
+}
+
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct GasReservationData {
+    id: u64,
+    reservation_id: ReservationId,
+    // Amount of gas reserved
+    amount: u64,
+    created_at: Blocks,
+    duration: Blocks,
+    // First block where the reservation is expired (created_at + duration)
+    expires_at: Blocks,
+    // Optional label to identify the reservation
+    label: Option<String>
+}
+
+impl GasReservationData { 
+    pub fn new(
+        id: u64,
+        reservation_id: ReservationId,
+        amount: u64,
+        created_at: Blocks,
+        duration: Blocks,
+        label: Option<String>
+    ) -> Self {
+        Self {
+            id,
+            reservation_id,
+            amount,
+            created_at,
+            duration,
+            expires_at: created_at.saturating_add(duration),
+            label
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn amount(&self) -> u64 {
+        self.amount
+    }
+
+    pub fn expires_at(&self) -> Blocks {
+        self.expires_at
+    }
+
+    pub fn label(&self) -> Option<&String> {
+        self.label.as_ref()
+    }
+
+    // Blocks in which the reservation can still be used (0 if it is expired)
+    pub fn blocks_remaining(&self, block_height: Blocks) -> Blocks {
+        self.expires_at.saturating_sub(block_height)
+    }
+
+    // A reservation can be used until the block before "expires_at",
+    // from that block it is expired (the runtime removes it)
+    pub fn is_expired(&self, block_height: Blocks) -> bool {
+        block_height >= self.expires_at
+    }
+
+    // The reservation is active, has at least "min_gas" and can still be used
+    // during "min_remaining_blocks" blocks
+    pub fn is_suitable(&self, min_gas: u64, min_remaining_blocks: Blocks, block_height: Blocks) -> bool {
+        !self.is_expired(block_height)
+            && self.amount >= min_gas
+            && self.blocks_remaining(block_height) >= min_remaining_blocks
+    }
+
+    // Unreserves the gas in the runtime, returns the amount of gas reclaimed
+    // The runtime already removed the expired reservations, so nothing is reclaimed
+    // for them
+    fn unreserve(&self) -> u64 {
+        self.reservation_id
+            .unreserve()
+            .unwrap_or(0)
+    }
+}
+
+// # Data of an active reservation (query)
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct ActiveGasReservation {
+    pub id: u64,
+    pub pool: PoolName,
+    pub amount: u64,
+    pub expires_at: Blocks,
+    pub label: Option<String>,
+    // Blocks in which the reservation can still be used
+    pub blocks_remaining: Blocks
+}
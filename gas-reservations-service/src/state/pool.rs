@@ -0,0 +1,231 @@
+use sails_rs::{
+    prelude::*,
+    collections::VecDeque
+};
+use super::{
+    Blocks,
+    GasReservationData,
+    ActiveGasReservation
+};
+
+// # Pool of gas reservations
+// Queue of reservations for one purpose, the reservations of a pool can only
+// be taken from that pool
+#[derive(Default, Clone)]
+pub struct GasReservationPool {
+    pub gas_reservations_data: VecDeque<GasReservationData>,
+    // The active reservations are below the low watermark of the pool (its
+    // "LowWatermark" event was already emitted)
+    pub below_low_watermark: bool
+}
+
+// # Status of a pool (query)
+#[derive(Encode, Decode, TypeInfo, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct PoolStatus {
+    pub active_reservations: u32,
+    // Gas of the active reservations
+    pub total_gas: u64
+}
+
+impl GasReservationPool {
+    pub fn push(&mut self, data: GasReservationData) {
+        self.gas_reservations_data.push_back(data);
+    }
+
+    // ### Takes the oldest active reservation
+    pub fn take(&mut self, block_height: Blocks) -> Option<GasReservationData> {
+        let index = self.gas_reservations_data
+            .iter()
+            .position(|data| !data.is_expired(block_height))?;
+
+        self.gas_reservations_data.remove(index)
+    }
+
+    // ### Puts a taken reservation back in the queue
+    // The queue is sorted by creation (id), so the reservation goes back to its
+    // place even if the queue changed after it was taken
+    pub fn restore(&mut self, data: GasReservationData) {
+        let index = self.gas_reservations_data
+            .iter()
+            .position(|other| other.id > data.id)
+            .unwrap_or(self.gas_reservations_data.len());
+
+        self.gas_reservations_data.insert(index, data);
+    }
+
+    // ### Takes the smallest reservation with enough gas and blocks remaining
+    // The other reservations are kept in the queue
+    pub fn take_best_fit(
+        &mut self,
+        min_gas: u64,
+        min_remaining_blocks: Blocks,
+        block_height: Blocks
+    ) -> Option<GasReservationData> {
+        let index = self.best_fit(min_gas, min_remaining_blocks, block_height)?;
+
+        self.gas_reservations_data.remove(index)
+    }
+
+    fn best_fit(&self, min_gas: u64, min_remaining_blocks: Blocks, block_height: Blocks) -> Option<usize> {
+        self.gas_reservations_data
+            .iter()
+            .enumerate()
+            .filter(|(_, data)| data.is_suitable(min_gas, min_remaining_blocks, block_height))
+            .min_by_key(|(_, data)| (data.amount, data.expires_at))
+            .map(|(index, _)| index)
+    }
+
+    // ### Removes the expired reservations from the pool and the runtime
+    // Returns the number of reservations removed and the amount of gas reclaimed
+    pub fn remove_expired(&mut self, block_height: Blocks) -> (u32, u64) {
+        let mut removed = 0;
+        let mut reclaimed_gas = 0;
+
+        self.gas_reservations_data.retain(|data| {
+            let expired = data.is_expired(block_height);
+
+            if expired {
+                removed += 1;
+                reclaimed_gas += data.unreserve();
+            }
+
+            !expired
+        });
+
+        (removed, reclaimed_gas)
+    }
+
+    // ### Removes the reservation with the given id from the pool and the runtime
+    // Returns the amount of gas reclaimed, or None if it is not in the pool
+    pub fn cancel(&mut self, id: u64) -> Option<u64> {
+        let index = self.gas_reservations_data
+            .iter()
+            .position(|data| data.id == id)?;
+
+        self.gas_reservations_data
+            .remove(index)
+            .map(|data| data.unreserve())
+    }
+
+    // Number of active reservations with at least "min_gas" that can still be
+    // used during "min_remaining_blocks" blocks
+    pub fn suitable_reservations(&self, min_gas: u64, min_remaining_blocks: Blocks, block_height: Blocks) -> u32 {
+        self.gas_reservations_data
+            .iter()
+            .filter(|data| data.is_suitable(min_gas, min_remaining_blocks, block_height))
+            .count() as u32
+    }
+
+    pub fn status(&self, block_height: Blocks) -> PoolStatus {
+        self.gas_reservations_data
+            .iter()
+            .filter(|data| !data.is_expired(block_height))
+            .fold(PoolStatus::default(), |status, data| PoolStatus {
+                active_reservations: status.active_reservations + 1,
+                total_gas: status.total_gas.saturating_add(data.amount)
+            })
+    }
+
+    pub fn active_info(&self, pool: &str, block_height: Blocks) -> Vec<ActiveGasReservation> {
+        self.gas_reservations_data
+            .iter()
+            .filter(|&data| !data.is_expired(block_height))
+            .map(|data| ActiveGasReservation {
+                id: data.id,
+                pool: pool.to_string(),
+                amount: data.amount,
+                expires_at: data.expires_at,
+                label: data.label.clone(),
+                blocks_remaining: data.blocks_remaining(block_height)
+            })
+            .collect()
+    }
+
+    pub fn expired(&self, block_height: Blocks) -> Vec<GasReservationData> {
+        self.gas_reservations_data
+            .iter()
+            .filter(|&data| data.is_expired(block_height))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gstd::ReservationId;
+
+    #[test]
+    fn best_fit_reservation_by_gas_and_remaining_blocks() {
+        let mut pool = GasReservationPool::default();
+
+        // (amount, created_at, duration)
+        let reservations = [(5_000, 0, 100), (1_000, 0, 100), (3_000, 0, 50), (3_000, 0, 20), (2_000, 0, 5)];
+
+        for (id, (amount, created_at, duration)) in reservations.into_iter().enumerate() {
+            pool.push(reservation(id as u64, amount, created_at, duration));
+        }
+
+        // The smallest reservation that has enough gas
+        assert_eq!(pool.best_fit(1_500, 0, 1), Some(4));
+
+        // The reservation with id 4 expires too soon, between the two with
+        // the same amount, the one that expires first
+        assert_eq!(pool.best_fit(1_500, 10, 1), Some(3));
+        assert_eq!(pool.best_fit(1_500, 30, 1), Some(2));
+
+        // The reservation with id 4 is expired
+        assert_eq!(pool.best_fit(1_500, 0, 5), Some(3));
+
+        // Must return None (no reservation has enough gas)
+        assert_eq!(pool.best_fit(6_000, 0, 1), None);
+
+        // The queue is not changed by the search
+        assert_eq!(pool.gas_reservations_data.len(), reservations.len());
+    }
+
+    #[test]
+    fn status_of_active_reservations() {
+        let mut pool = GasReservationPool::default();
+
+        pool.push(reservation(0, 1_000, 0, 10));
+        pool.push(reservation(1, 2_000, 0, 20));
+
+        assert_eq!(pool.status(5), PoolStatus { active_reservations: 2, total_gas: 3_000 });
+
+        // The first reservation expired
+        assert_eq!(pool.status(10), PoolStatus { active_reservations: 1, total_gas: 2_000 });
+        assert_eq!(pool.expired(10), vec![reservation(0, 1_000, 0, 10)]);
+    }
+
+    #[test]
+    fn restored_reservation_keeps_its_place() {
+        let mut pool = GasReservationPool::default();
+
+        pool.push(reservation(0, 1_000, 0, 5));
+        pool.push(reservation(1, 1_000, 0, 20));
+        pool.push(reservation(2, 1_000, 0, 20));
+        pool.push(reservation(3, 1_000, 0, 20));
+
+        // The first reservation is expired, the oldest active one is taken
+        let data = pool.take(5).expect("There is an active reservation");
+        assert_eq!(data.id(), 1);
+
+        // The queue changes while the reservation is out of it
+        let other = pool.take(5).expect("There is an active reservation");
+        assert_eq!(other.id(), 2);
+        pool.gas_reservations_data.pop_front();
+
+        pool.restore(data);
+        pool.restore(other);
+
+        let ids: Vec<u64> = pool.gas_reservations_data.iter().map(|data| data.id()).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    fn reservation(id: u64, amount: u64, created_at: Blocks, duration: Blocks) -> GasReservationData {
+        GasReservationData::new(id, ReservationId::from([id as u8; 32]), amount, created_at, duration, None)
+    }
+}
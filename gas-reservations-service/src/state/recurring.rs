@@ -0,0 +1,231 @@
+use sails_rs::{
+    prelude::*,
+    gstd::{exec, msg}
+};
+use crate::service_enums::GasReservationError;
+use super::{
+    Blocks,
+    GasReservationState,
+    JobId,
+    scheduler::{
+        MAX_FINISHED_JOBS,
+        MIN_WAKE_UP_GAS,
+        SCHEDULER_POOL
+    }
+};
+
+// Method that receives the wake-up messages of the recurring jobs
+pub const RUN_RECURRING_JOB_METHOD: &str = "RunRecurringJob";
+
+#[derive(Encode, Decode, TypeInfo, Clone, Copy, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum JobStopReason {
+    // There is no reservation in the scheduler pool for the next run
+    ReservationPoolEmpty,
+    // The wake-up message of the next run could not be sent
+    SchedulingFailed
+}
+
+#[derive(Encode, Decode, TypeInfo, Clone, Copy, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum RecurringJobStatus {
+    Active,
+    Stopped(JobStopReason),
+    Cancelled
+}
+
+// # Recurring job
+// Each "period_blocks" blocks a wake-up message runs the handler (a route of
+// other service of this program) and schedules the next run with a fresh reservation
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct RecurringJob {
+    pub id: JobId,
+    pub handler_service: String,
+    pub handler_method: String,
+    // Encoded arguments of the handler
+    pub payload: Vec<u8>,
+    pub period_blocks: Blocks,
+    pub status: RecurringJobStatus,
+    pub runs: u32,
+    // Runs where the handler message could not be sent
+    pub failed_runs: u32,
+    pub last_run: Option<Blocks>,
+    pub next_run: Option<Blocks>,
+    // Nonce of the wake-up message of the next run
+    pub wake_up_nonce: u64
+}
+
+// # Health of a recurring job (query)
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct JobHealth {
+    pub id: JobId,
+    pub status: RecurringJobStatus,
+    pub runs: u32,
+    pub failed_runs: u32,
+    pub last_run: Option<Blocks>,
+    pub next_run: Option<Blocks>,
+    // Reservations of the scheduler pool that can be used for the next runs
+    pub reservations_left: u32
+}
+
+// ## Methods of the recurring jobs
+impl GasReservationState {
+    // ### Registers a recurring job and schedules its first run
+    // The handler can not be a route of this service (the handler messages are
+    // sent by the program, so they would pass the checks of the wake-up
+    // messages). Returns the job
+    pub fn register_recurring_job(
+        &mut self,
+        handler_service: String,
+        handler_method: String,
+        payload: Vec<u8>,
+        period_blocks: Blocks
+    ) -> Result<RecurringJob, GasReservationError> {
+        if period_blocks == 0 {
+            return Err(GasReservationError::ZeroBlocks);
+        }
+
+        if handler_service == self.service_route {
+            return Err(GasReservationError::InvalidJobHandler);
+        }
+
+        let job_id = self.current_job_id;
+        let next_job_id = job_id
+            .checked_add(1)
+            .ok_or(GasReservationError::IdOverflow)?;
+
+        let wake_up_nonce = self.send_wake_up(RUN_RECURRING_JOB_METHOD, job_id, period_blocks)?;
+
+        let job = RecurringJob {
+            id: job_id,
+            handler_service,
+            handler_method,
+            payload,
+            period_blocks,
+            status: RecurringJobStatus::Active,
+            runs: 0,
+            failed_runs: 0,
+            last_run: None,
+            next_run: Some(exec::block_height().saturating_add(period_blocks)),
+            wake_up_nonce
+        };
+
+        self.recurring_jobs.insert(job_id, job.clone());
+        self.current_job_id = next_job_id;
+
+        Ok(job)
+    }
+
+    // ### Runs a recurring job (wake-up message)
+    // Sends the message to the handler and schedules the next run, if there is
+    // no reservation for the next run, the job is stopped and moved to the
+    // finished recurring jobs. A wake-up message without the nonce that the job
+    // expects is ignored. Returns the job
+    pub fn run_recurring_job(&mut self, job_id: JobId, nonce: u64) -> Result<RecurringJob, GasReservationError> {
+        let job = self.recurring_jobs
+            .get(&job_id)
+            .ok_or(GasReservationError::JobDoesNotExists)?
+            .clone();
+
+        if nonce != job.wake_up_nonce {
+            return Err(GasReservationError::InvalidWakeUp);
+        }
+
+        let block_height = exec::block_height();
+        let mut handler_payload = (job.handler_service.as_str(), job.handler_method.as_str()).encode();
+        handler_payload.extend_from_slice(&job.payload);
+
+        let handler_sent = msg::send_bytes(exec::program_id(), handler_payload, 0).is_ok();
+        let next_run = self.send_wake_up(RUN_RECURRING_JOB_METHOD, job_id, job.period_blocks);
+
+        let mut job = self.recurring_jobs
+            .remove(&job_id)
+            .ok_or(GasReservationError::JobDoesNotExists)?;
+
+        job.runs = job.runs.saturating_add(1);
+        job.last_run = Some(block_height);
+
+        if !handler_sent {
+            job.failed_runs = job.failed_runs.saturating_add(1);
+        }
+
+        match next_run {
+            Ok(nonce) => {
+                job.next_run = Some(block_height.saturating_add(job.period_blocks));
+                job.wake_up_nonce = nonce;
+
+                self.recurring_jobs.insert(job_id, job.clone());
+            },
+            Err(e) => {
+                let reason = match e {
+                    GasReservationError::NoReservationForJob => JobStopReason::ReservationPoolEmpty,
+                    _ => JobStopReason::SchedulingFailed
+                };
+
+                job.status = RecurringJobStatus::Stopped(reason);
+                job.next_run = None;
+
+                self.push_finished_recurring_job(job.clone());
+            }
+        }
+
+        Ok(job)
+    }
+
+    // ### Cancels a recurring job
+    // The job is moved to the finished recurring jobs, so the wake-up message
+    // of its next run is ignored
+    pub fn cancel_recurring_job(&mut self, job_id: JobId) -> Result<(), GasReservationError> {
+        let mut job = self.recurring_jobs
+            .remove(&job_id)
+            .ok_or(GasReservationError::JobDoesNotExists)?;
+
+        job.status = RecurringJobStatus::Cancelled;
+        job.next_run = None;
+
+        self.push_finished_recurring_job(job);
+
+        Ok(())
+    }
+
+    // Keeps the cancelled or stopped job, the oldest one is dropped if there
+    // are "MAX_FINISHED_JOBS" finished recurring jobs
+    fn push_finished_recurring_job(&mut self, job: RecurringJob) {
+        if self.finished_recurring_jobs.len() >= MAX_FINISHED_JOBS {
+            self.finished_recurring_jobs.pop_front();
+        }
+
+        self.finished_recurring_jobs.push_back(job);
+    }
+
+    // Health of the active recurring jobs and the last finished ones, sorted by id
+    pub fn job_health(&self) -> Vec<JobHealth> {
+        let block_height = exec::block_height();
+        let mut health: Vec<JobHealth> = self.recurring_jobs
+            .values()
+            .chain(self.finished_recurring_jobs.iter())
+            .map(|job| JobHealth {
+                id: job.id,
+                status: job.status,
+                runs: job.runs,
+                failed_runs: job.failed_runs,
+                last_run: job.last_run,
+                next_run: job.next_run,
+                reservations_left: self.pools
+                    .get(SCHEDULER_POOL)
+                    .map(|pool| pool.suitable_reservations(MIN_WAKE_UP_GAS, job.period_blocks, block_height))
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        health.sort_by_key(|job| job.id);
+
+        health
+    }
+}
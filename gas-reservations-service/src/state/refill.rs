@@ -0,0 +1,172 @@
+use sails_rs::{
+    prelude::*,
+    gstd::exec
+};
+use crate::service_enums::{
+    GasReservationError,
+    RuntimeErrorKind
+};
+use super::{
+    Blocks,
+    PoolName,
+    GasReservationState,
+    MAX_POOL_NAME_LENGTH,
+    MAX_RESERVATIONS_PER_PROGRAM
+};
+
+// Gas that is kept for the rest of the message when the pools are refilled, until
+// the admins set other margin ("set_refill_gas_margin")
+pub const DEFAULT_REFILL_GAS_MARGIN: u64 = 2_000_000_000;
+
+// # Refill policy of a pool
+// The pool must hold at least "reservations" active reservations of at least
+// "gas_amount" gas that can still be used during "min_remaining_blocks" blocks
+#[derive(Encode, Decode, TypeInfo, Clone, Copy, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct RefillPolicy {
+    pub reservations: u32,
+    pub gas_amount: u64,
+    pub min_remaining_blocks: Blocks,
+    // Blocks of the new reservations, must be greater than "min_remaining_blocks"
+    pub reservation_blocks: Blocks
+}
+
+// # Result of a refill
+#[derive(Encode, Decode, TypeInfo, Clone, Default, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct RefillReport {
+    // Reservations created
+    pub created: u32,
+    // Reservations that could not be created (not enough gas in the message)
+    pub missing: u32,
+    // Pools whose refill stopped by other error
+    pub failed: Vec<RefillFailure>
+}
+
+// # Pool whose refill stopped by an error that is not the lack of gas
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct RefillFailure {
+    pub pool: PoolName,
+    pub error: GasReservationError,
+    // Reservations of the pool that were not created
+    pub not_created: u32
+}
+
+impl RefillPolicy {
+    pub fn is_valid(&self) -> bool {
+        self.reservations > 0
+            && self.reservations <= MAX_RESERVATIONS_PER_PROGRAM
+            && self.gas_amount > 0
+            && self.reservation_blocks > self.min_remaining_blocks
+    }
+}
+
+// ## Methods to refill the pools
+impl GasReservationState {
+    pub fn set_refill_policy(&mut self, pool: PoolName, policy: RefillPolicy) -> Result<(), GasReservationError> {
+        if !policy.is_valid() {
+            return Err(GasReservationError::InvalidRefillPolicy);
+        }
+
+        if pool.len() > MAX_POOL_NAME_LENGTH {
+            return Err(GasReservationError::LimitExceeded);
+        }
+
+        self.refill_policies.insert(pool, policy);
+
+        Ok(())
+    }
+
+    pub fn remove_refill_policy(&mut self, pool: &str) -> Result<(), GasReservationError> {
+        self.refill_policies
+            .remove(pool)
+            .map(|_| ())
+            .ok_or(GasReservationError::RefillPolicyDoesNotExists)
+    }
+
+    pub fn set_refill_gas_margin(&mut self, margin: u64) {
+        self.refill_gas_margin = margin;
+    }
+
+    // Refill policies sorted by pool name
+    pub fn refill_policies(&self) -> Vec<(PoolName, RefillPolicy)> {
+        let mut policies: Vec<(PoolName, RefillPolicy)> = self.refill_policies
+            .iter()
+            .map(|(pool, policy)| (pool.clone(), *policy))
+            .collect();
+
+        policies.sort_by(|a, b| a.0.cmp(&b.0));
+
+        policies
+    }
+
+    // ### Refills the pools with a refill policy
+    // The expired reservations of the pools are removed, and new reservations are
+    // created while the message has gas for them (keeping "refill_gas_margin" for
+    // the rest of the message). The lack of gas stops the refill of the pool and
+    // its remaining reservations are reported as missing, other errors stop the
+    // refill of the pool and are reported as failures. The reservations of all the
+    // pools are kept under the max reservations of the runtime, the ones over it are
+    // reported as failures too. Extending services can call it at the end of any
+    // message to keep the pools filled
+    pub fn maintain() -> RefillReport {
+        let state = Self::state_mut();
+        let block_height = exec::block_height();
+        let mut report = RefillReport::default();
+
+        state.remove_expired_gas_reservations();
+
+        let mut free_capacity = MAX_RESERVATIONS_PER_PROGRAM.saturating_sub(state.tracked_reservations());
+
+        for (pool_name, policy) in state.refill_policies() {
+            let suitable = state.pools
+                .get(&pool_name)
+                .map(|pool| pool.suitable_reservations(policy.gas_amount, policy.min_remaining_blocks, block_height))
+                .unwrap_or_default();
+            let needed = policy.reservations.saturating_sub(suitable);
+
+            for attempt in 0..needed {
+                let not_created = needed - attempt;
+
+                if free_capacity == 0 {
+                    report.failed.push(RefillFailure {
+                        pool: pool_name.clone(),
+                        error: GasReservationError::ReservationFailed(RuntimeErrorKind::ReservationsLimitReached),
+                        not_created
+                    });
+                    break;
+                }
+
+                if exec::gas_available() < policy.gas_amount.saturating_add(state.refill_gas_margin) {
+                    report.missing += not_created;
+                    break;
+                }
+
+                match state.create_pool_reservation(&pool_name, policy.gas_amount, policy.reservation_blocks, None) {
+                    Ok(_) => {
+                        report.created += 1;
+                        free_capacity -= 1;
+                    },
+                    Err(GasReservationError::ReservationFailed(RuntimeErrorKind::NotEnoughGas)) => {
+                        report.missing += not_created;
+                        break;
+                    },
+                    Err(error) => {
+                        report.failed.push(RefillFailure {
+                            pool: pool_name.clone(),
+                            error,
+                            not_created
+                        });
+                        break;
+                    }
+                }
+            }
+        }
+
+        report
+    }
+}
@@ -0,0 +1,230 @@
+use sails_rs::{
+    prelude::*,
+    gstd::{exec, msg}
+};
+use crate::service_enums::GasReservationError;
+use super::{
+    Blocks,
+    GasReservationState
+};
+
+pub type JobId = u64;
+
+// Pool of the reservations used by the scheduler
+pub const SCHEDULER_POOL: &str = "scheduler";
+// Route of the service, used by the wake-up messages (change it with
+// "set_service_route" if the service is exposed with other route)
+pub const DEFAULT_SERVICE_ROUTE: &str = "GasReservationService";
+// Method that receives the wake-up messages of the jobs
+pub const DISPATCH_JOB_METHOD: &str = "DispatchJob";
+// Min gas of the reservations used by the wake-up messages. A wake-up message
+// burns about 2_500_000_000 gas (3_200_000_000 for a recurring job, that schedules
+// its next run), the gas of the calls and handlers that it sends is added to it
+pub const MIN_WAKE_UP_GAS: u64 = 5_000_000_000;
+// Max finished jobs kept with their final status (the oldest ones are dropped)
+pub const MAX_FINISHED_JOBS: usize = 100;
+
+// # Call to a service of other program
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct ScheduledCall {
+    pub target: ActorId,
+    pub service: String,
+    pub method: String,
+    // Encoded arguments of the method
+    pub payload: Vec<u8>,
+    // Value sent with the call (from the balance of the program)
+    pub value: u128
+}
+
+#[derive(Encode, Decode, TypeInfo, Clone, Copy, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum JobStatus {
+    Scheduled,
+    Dispatched,
+    Cancelled,
+    // The call could not be sent (for example, not enough value in the program)
+    DispatchFailed
+}
+
+// # Job of the scheduler
+// A delayed wake-up message is sent to the program from a reservation, and
+// the call is sent when the wake-up message arrives (if the job was not cancelled)
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct Job {
+    pub id: JobId,
+    pub call: ScheduledCall,
+    pub scheduled_at: Blocks,
+    pub dispatch_at: Blocks,
+    pub status: JobStatus,
+    // Nonce of the wake-up message of the job
+    pub wake_up_nonce: u64
+}
+
+impl ScheduledCall {
+    // Sails payload of the call: service route, method and arguments
+    pub fn sails_payload(&self) -> Vec<u8> {
+        let mut payload = (self.service.as_str(), self.method.as_str()).encode();
+        payload.extend_from_slice(&self.payload);
+
+        payload
+    }
+}
+
+// ## Methods of the scheduler
+impl GasReservationState {
+    // Sets the route of the service that receives the wake-up messages
+    pub fn set_service_route(route: &str) {
+        Self::state_mut().service_route = route.to_string();
+    }
+
+    // ### Schedules a call after "delay_blocks" blocks
+    // Takes a reservation of the scheduler pool that can still be used during
+    // the delay, and sends the wake-up message from it. Returns the job
+    pub fn schedule_call(&mut self, call: ScheduledCall, delay_blocks: Blocks) -> Result<Job, GasReservationError> {
+        if delay_blocks == 0 {
+            return Err(GasReservationError::ZeroBlocks);
+        }
+
+        let job_id = self.current_job_id;
+        let next_job_id = job_id
+            .checked_add(1)
+            .ok_or(GasReservationError::IdOverflow)?;
+
+        let block_height = exec::block_height();
+
+        let wake_up_nonce = self.send_wake_up(DISPATCH_JOB_METHOD, job_id, delay_blocks)?;
+
+        let job = Job {
+            id: job_id,
+            call,
+            scheduled_at: block_height,
+            dispatch_at: block_height.saturating_add(delay_blocks),
+            status: JobStatus::Scheduled,
+            wake_up_nonce
+        };
+
+        self.jobs.insert(job_id, job.clone());
+        self.current_job_id = next_job_id;
+
+        Ok(job)
+    }
+
+    // ### Sends a delayed wake-up message for the job to the given method of the service
+    // The message is sent from a reservation of the scheduler pool with at least
+    // "MIN_WAKE_UP_GAS" that can still be used during the delay. The reservation
+    // is only consumed if the message is sent, otherwise it is kept in the pool.
+    // Returns the nonce of the message, that the job must expect
+    pub(super) fn send_wake_up(&mut self, method: &str, job_id: JobId, delay_blocks: Blocks) -> Result<u64, GasReservationError> {
+        let nonce = self.wake_up_nonce;
+        let wake_up = (self.service_route.as_str(), method, job_id, nonce).encode();
+
+        Self::lease_pool_reservation_for(SCHEDULER_POOL, MIN_WAKE_UP_GAS, delay_blocks)
+            .ok_or(GasReservationError::NoReservationForJob)?
+            .consume_if(None, |reservation_id| {
+                msg::send_bytes_delayed_from_reservation(reservation_id, exec::program_id(), wake_up, 0, delay_blocks)
+                    .map_err(|e| GasReservationError::JobSchedulingFailed(e.into()))
+            })?;
+
+        self.wake_up_nonce = nonce.wrapping_add(1);
+
+        Ok(nonce)
+    }
+
+    // ### Sends the call of a job (wake-up message)
+    // Returns the final status of the job, cancelled jobs are not sent. The
+    // wake-up message is the last message of the job, so the job is moved to
+    // the finished jobs. A wake-up message without the nonce of the job is ignored
+    pub fn dispatch_job(&mut self, job_id: JobId, nonce: u64) -> Result<JobStatus, GasReservationError> {
+        let expected_nonce = self.jobs
+            .get(&job_id)
+            .ok_or(GasReservationError::JobDoesNotExists)?
+            .wake_up_nonce;
+
+        if nonce != expected_nonce {
+            return Err(GasReservationError::InvalidWakeUp);
+        }
+
+        let mut job = self.jobs
+            .remove(&job_id)
+            .ok_or(GasReservationError::JobDoesNotExists)?;
+
+        if job.status == JobStatus::Scheduled {
+            job.status = match msg::send_bytes(job.call.target, job.call.sails_payload(), job.call.value) {
+                Ok(_) => JobStatus::Dispatched,
+                Err(_) => JobStatus::DispatchFailed
+            };
+        }
+
+        let status = job.status;
+
+        self.push_finished_job(job);
+
+        Ok(status)
+    }
+
+    // Keeps the job with its final status, the oldest finished job is dropped
+    // if there are "MAX_FINISHED_JOBS" finished jobs
+    fn push_finished_job(&mut self, job: Job) {
+        if self.finished_jobs.len() >= MAX_FINISHED_JOBS {
+            self.finished_jobs.pop_front();
+        }
+
+        self.finished_jobs.push_back(job);
+    }
+
+    // Job waiting for its wake-up message, or one of the last finished jobs
+    pub fn job(&self, job_id: JobId) -> Option<&Job> {
+        self.jobs
+            .get(&job_id)
+            .or_else(|| self.finished_jobs.iter().find(|job| job.id == job_id))
+    }
+
+    // ### Cancels a scheduled job or a recurring job
+    // The runtime can not remove the wake-up message, but the call is not sent
+    // (the job is finished when its wake-up message arrives)
+    pub fn cancel_job(&mut self, job_id: JobId) -> Result<(), GasReservationError> {
+        if self.recurring_jobs.contains_key(&job_id) {
+            return self.cancel_recurring_job(job_id);
+        }
+
+        let finished = self.finished_jobs
+            .iter()
+            .map(|job| job.id)
+            .chain(self.finished_recurring_jobs.iter().map(|job| job.id))
+            .any(|id| id == job_id);
+
+        if finished {
+            return Err(GasReservationError::JobIsNotScheduled);
+        }
+
+        let job = self.jobs
+            .get_mut(&job_id)
+            .ok_or(GasReservationError::JobDoesNotExists)?;
+
+        if job.status != JobStatus::Scheduled {
+            return Err(GasReservationError::JobIsNotScheduled);
+        }
+
+        job.status = JobStatus::Cancelled;
+
+        Ok(())
+    }
+
+    // Jobs with the given status, sorted by id
+    pub fn jobs_with_status(&self, status: JobStatus) -> Vec<Job> {
+        let mut jobs: Vec<Job> = self.jobs
+            .values()
+            .filter(|job| job.status == status)
+            .cloned()
+            .collect();
+
+        jobs.sort_by_key(|job| job.id);
+
+        jobs
+    }
+}
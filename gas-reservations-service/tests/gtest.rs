@@ -0,0 +1,747 @@
+use gtest::{constants::{DEFAULT_USER_ALICE, UNITS}, Program, System};
+use gas_reservations_service::{
+    service_enums::{GasReservationError, RuntimeErrorKind},
+    services::ContractResponse,
+    state::{
+        ActiveGasReservation,
+        BatchReport,
+        BatchStopReason,
+        JobHealth,
+        JobStatus,
+        JobStopReason,
+        PoolStatus,
+        RecurringJobStatus,
+        RefillFailure,
+        RefillPolicy,
+        RefillReport
+    }
+};
+use sails_rs::{
+    scale_codec::{Decode, Encode},
+    ActorId
+};
+use std::cell::RefCell;
+
+const ADMIN: u64 = DEFAULT_USER_ALICE;
+const SERVICE: &str = "GasReservationService";
+const GAS_TO_RESERVE: u64 = 1_000_000_000;
+
+// # Test environment
+// Program with the gas reservation service deployed by the ADMIN account
+struct Env {
+    system: System,
+    // Events emitted in the block of the last calls
+    events: RefCell<Vec<Vec<u8>>>
+}
+
+impl Env {
+    fn new() -> Self {
+        let system = System::new();
+
+        let program = Program::from_binary_with_id(&system, 100, gas_reservations_service_wasm::WASM_BINARY);
+        program.send_bytes(ADMIN, "New".encode());
+        system.run_next_block();
+
+        Self { system, events: RefCell::new(Vec::new()) }
+    }
+
+    fn program(&self) -> Program<'_> {
+        self.system
+            .get_program(100)
+            .expect("Program is not deployed")
+    }
+
+    // ## Sends sails messages to the service in the same block and decodes the replies
+    fn calls_in_same_block(&self, from: ActorId, calls: &[(&str, Vec<u8>)]) -> Vec<ContractResponse> {
+        if self.system.balance_of(from) == 0 {
+            self.system.mint_to(from, 1_000 * UNITS);
+        }
+
+        let message_ids: Vec<_> = calls
+            .iter()
+            .map(|(method, args)| {
+                let mut payload = (SERVICE, *method).encode();
+                payload.extend_from_slice(args);
+
+                self.program().send_bytes(from, payload)
+            })
+            .collect();
+
+        let result = self.system.run_next_block();
+
+        *self.events.borrow_mut() = result
+            .log()
+            .iter()
+            .filter(|log| log.destination() == ActorId::zero())
+            .map(|log| log.payload().to_vec())
+            .collect();
+
+        calls
+            .iter()
+            .zip(message_ids)
+            .map(|((method, _), message_id)| {
+                let reply = result
+                    .log()
+                    .iter()
+                    .find(|log| log.reply_to() == Some(message_id))
+                    .expect("The program did not reply");
+
+                let route = (SERVICE, *method).encode();
+                let payload = reply.payload();
+
+                assert!(payload.starts_with(&route), "Reply without the service route");
+
+                ContractResponse::decode(&mut &payload[route.len()..])
+                    .expect("Error while decoding the reply")
+            })
+            .collect()
+    }
+
+    fn call<A: Encode>(&self, from: ActorId, method: &str, args: A) -> ContractResponse {
+        self.calls_in_same_block(from, &[(method, args.encode())])
+            .remove(0)
+    }
+
+    fn new_gas_reservation(&self, blocks: u32) -> ContractResponse {
+        self.call(ADMIN.into(), "NewGasReservation", (GAS_TO_RESERVE, blocks, None::<String>))
+    }
+
+    // ## Names of the events emitted in the block of the last calls
+    fn event_names(&self) -> Vec<String> {
+        self.events
+            .borrow()
+            .iter()
+            .map(|payload| {
+                let (_, name) = <(String, String)>::decode(&mut payload.as_slice())
+                    .expect("Error while decoding the event");
+
+                name
+            })
+            .collect()
+    }
+
+    // ## Runs the given blocks, returns the payloads of the messages sent to the destination
+    fn messages_to(&self, destination: ActorId, blocks: u32) -> Vec<Vec<u8>> {
+        (0..blocks)
+            .flat_map(|_| {
+                self.system
+                    .run_next_block()
+                    .log()
+                    .iter()
+                    .filter(|log| log.destination() == destination)
+                    .map(|log| log.payload().to_vec())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    // ## Leases the oldest reservation of the pool and fails before using it
+    // Returns the internal id of the leased reservation
+    fn lease_and_fail(&self, pool: &str) -> Option<u64> {
+        let route = ("LeaseCheckService", "LeaseAndFail").encode();
+        let mut payload = route.clone();
+        payload.extend_from_slice(&pool.encode());
+
+        let message_id = self.program().send_bytes(ADMIN, payload);
+        let result = self.system.run_next_block();
+
+        *self.events.borrow_mut() = result
+            .log()
+            .iter()
+            .filter(|log| log.destination() == ActorId::zero())
+            .map(|log| log.payload().to_vec())
+            .collect();
+
+        let reply = result
+            .log()
+            .iter()
+            .find(|log| log.reply_to() == Some(message_id))
+            .expect("The program did not reply");
+
+        Option::<u64>::decode(&mut &reply.payload()[route.len()..])
+            .expect("Error while decoding the reply")
+    }
+
+    // ## Active and expired reservations, queried in the same block
+    fn reservations_count(&self) -> (u32, usize) {
+        let mut replies = self.calls_in_same_block(ADMIN.into(), &[
+            ("TotalActiveGasReservations", Vec::new()),
+            ("ExpiredGasReservations", Vec::new())
+        ]);
+
+        let ContractResponse::ExpiredGasReservations(expired) = replies.remove(1) else {
+            panic!("Expired reservations expected");
+        };
+
+        let ContractResponse::ActiveGasReservations(active) = replies.remove(0) else {
+            panic!("Active reservations expected");
+        };
+
+        (active, expired.len())
+    }
+}
+
+#[test]
+fn reservations_expire_at_the_same_block_in_all_queries() {
+    let env = Env::new();
+    let blocks = 3;
+
+    assert_eq!(env.new_gas_reservation(blocks), ContractResponse::ReservationCreated);
+
+    // The reservation was created in the previous block, it is active
+    // during "blocks" blocks (the creation block included)
+    for _ in 1..blocks {
+        assert_eq!(env.reservations_count(), (1, 0));
+    }
+
+    // From the expiry block, all the queries agree that it is expired
+    assert_eq!(env.reservations_count(), (0, 1));
+    assert_eq!(env.reservations_count(), (0, 1));
+}
+
+#[test]
+fn remove_expired_gas_reservations_keeps_active_ones() {
+    let env = Env::new();
+
+    assert_eq!(env.new_gas_reservation(2), ContractResponse::ReservationCreated);
+    assert_eq!(env.new_gas_reservation(100), ContractResponse::ReservationCreated);
+
+    // The first reservation expired
+    env.system.run_next_block();
+    assert_eq!(env.reservations_count(), (1, 1));
+
+    let result = env.call(ADMIN.into(), "RemoveExpiredGasReservation", ());
+    assert!(
+        matches!(result, ContractResponse::ExpiredGasReservationDeleted { .. }),
+        "Expired reservations must be deleted"
+    );
+
+    // Only the active reservation is kept
+    assert_eq!(env.reservations_count(), (1, 0));
+}
+
+#[test]
+fn active_gas_reservations_with_amount_expiry_and_label() {
+    let env = Env::new();
+    let label = Some("rewards".to_string());
+
+    let result = env.call(ADMIN.into(), "NewGasReservation", (GAS_TO_RESERVE, 10u32, label.clone()));
+    assert_eq!(result, ContractResponse::ReservationCreated);
+
+    // Created in the last block, the query is sent two blocks later
+    let created_at = env.system.block_height();
+    env.system.run_next_block();
+
+    let result = env.call(ADMIN.into(), "ActiveGasReservations", ());
+    assert_eq!(result, ContractResponse::ActiveGasReservationsData(vec![
+        ActiveGasReservation {
+            id: 0,
+            pool: "default".to_string(),
+            amount: GAS_TO_RESERVE,
+            expires_at: created_at + 10,
+            label,
+            blocks_remaining: 8
+        }
+    ]));
+}
+
+#[test]
+fn pools_keep_their_own_reservations() {
+    let env = Env::new();
+    let pool = "rewards".to_string();
+
+    for _ in 0..2 {
+        let result = env.call(ADMIN.into(), "NewPoolGasReservation", (pool.clone(), GAS_TO_RESERVE, 10u32, None::<String>));
+        assert_eq!(result, ContractResponse::PoolReservationCreated(pool.clone()));
+    }
+
+    assert_eq!(env.new_gas_reservation(10), ContractResponse::ReservationCreated);
+
+    let result = env.call(ADMIN.into(), "PoolStatus", pool.clone());
+    assert_eq!(result, ContractResponse::PoolStatus(PoolStatus { active_reservations: 2, total_gas: 2 * GAS_TO_RESERVE }));
+
+    // The queries of the reservations cover all the pools
+    assert_eq!(env.reservations_count(), (3, 0));
+
+    let result = env.call(ADMIN.into(), "ActiveGasReservations", ());
+    let ContractResponse::ActiveGasReservationsData(reservations) = result else {
+        panic!("Active reservations expected");
+    };
+    let pools: Vec<&str> = reservations
+        .iter()
+        .map(|reservation| reservation.pool.as_str())
+        .collect();
+    assert_eq!(pools, vec!["default", "rewards", "rewards"]);
+
+    let result = env.call(ADMIN.into(), "Pools", ());
+    assert_eq!(result, ContractResponse::Pools(vec!["default".to_string(), pool.clone()]));
+
+    // Ids are unique in all the pools
+    let result = env.call(ADMIN.into(), "CancelGasReservation", 0u64);
+    assert!(
+        matches!(result, ContractResponse::GasReservationCancelled { id: 0, .. }),
+        "The reservation must be cancelled"
+    );
+
+    let result = env.call(ADMIN.into(), "PoolStatus", pool);
+    assert_eq!(result, ContractResponse::PoolStatus(PoolStatus { active_reservations: 1, total_gas: GAS_TO_RESERVE }));
+}
+
+#[test]
+fn reservations_are_taken_from_a_named_pool() {
+    let env = Env::new();
+    let pool = "rewards".to_string();
+    let stranger = ActorId::from(1);
+
+    env.call(ADMIN.into(), "SetConsumptionLogCapacity", 10u32);
+
+    for _ in 0..2 {
+        env.call(ADMIN.into(), "NewPoolGasReservation", (pool.clone(), GAS_TO_RESERVE, 10u32, None::<String>));
+    }
+
+    // Must return an error (only admins and allowed accounts)
+    let result = env.call(stranger, "TakePoolReservation", (pool.clone(), None::<String>));
+    assert_eq!(result, ContractResponse::Error(GasReservationError::ActionOnlyForAllowedAccounts));
+
+    // The oldest reservation of the pool is taken
+    let result = env.call(ADMIN.into(), "TakePoolReservation", (pool.clone(), Some("payout".to_string())));
+    assert_eq!(result, ContractResponse::PoolReservationTaken { pool: pool.clone(), id: 0, amount: GAS_TO_RESERVE });
+
+    let result = env.call(ADMIN.into(), "PoolStatus", pool.clone());
+    assert_eq!(result, ContractResponse::PoolStatus(PoolStatus { active_reservations: 1, total_gas: GAS_TO_RESERVE }));
+
+    let ContractResponse::ConsumptionLog(page) = env.call(ADMIN.into(), "ConsumptionLog", (0u32, 10u32)) else {
+        panic!("Consumption log expected");
+    };
+
+    assert_eq!(page.records[0].reservation_id, 0);
+    assert_eq!(page.records[0].tag, Some("payout".to_string()));
+
+    // Must return an error (the pool has no reservations)
+    let result = env.call(ADMIN.into(), "TakePoolReservation", ("empty".to_string(), None::<String>));
+    assert_eq!(result, ContractResponse::Error(GasReservationError::ReservationDoesNotExists));
+}
+
+#[test]
+fn refill_pools_to_the_policy_level() {
+    let env = Env::new();
+    let pool = "default".to_string();
+    let policy = RefillPolicy {
+        reservations: 3,
+        gas_amount: GAS_TO_RESERVE,
+        min_remaining_blocks: 5,
+        reservation_blocks: 10
+    };
+
+    // Must return an error (the reservations must last more than the min blocks)
+    let result = env.call(ADMIN.into(), "SetRefillPolicy", (pool.clone(), RefillPolicy { reservation_blocks: 5, ..policy }));
+    assert_eq!(result, ContractResponse::Error(GasReservationError::InvalidRefillPolicy));
+
+    // Must return an error (more reservations than the max of the runtime)
+    let result = env.call(ADMIN.into(), "SetRefillPolicy", (pool.clone(), RefillPolicy { reservations: 257, ..policy }));
+    assert_eq!(result, ContractResponse::Error(GasReservationError::InvalidRefillPolicy));
+
+    let result = env.call(ADMIN.into(), "SetRefillPolicy", (pool.clone(), policy));
+    assert_eq!(result, ContractResponse::RefillPolicySet(pool.clone()));
+
+    let result = env.call(ADMIN.into(), "Refill", ());
+    assert_eq!(result, ContractResponse::Refilled(RefillReport { created: 3, missing: 0, failed: Vec::new() }));
+    assert_eq!(env.reservations_count(), (3, 0));
+
+    // The pool is at the policy level
+    let result = env.call(ADMIN.into(), "Refill", ());
+    assert_eq!(result, ContractResponse::Refilled(RefillReport { created: 0, missing: 0, failed: Vec::new() }));
+
+    // The reservations can not be used during 5 more blocks
+    for _ in 0..3 {
+        env.system.run_next_block();
+    }
+
+    let result = env.call(ADMIN.into(), "Refill", ());
+    assert_eq!(result, ContractResponse::Refilled(RefillReport { created: 3, missing: 0, failed: Vec::new() }));
+    assert_eq!(env.reservations_count(), (6, 0));
+
+    // The message does not have gas for these reservations
+    let result = env.call(ADMIN.into(), "SetRefillPolicy", ("big".to_string(), RefillPolicy { gas_amount: u64::MAX / 2, ..policy }));
+    assert_eq!(result, ContractResponse::RefillPolicySet("big".to_string()));
+
+    let result = env.call(ADMIN.into(), "Refill", ());
+    assert_eq!(result, ContractResponse::Refilled(RefillReport { created: 0, missing: 3, failed: Vec::new() }));
+
+    let result = env.call(ADMIN.into(), "RemoveRefillPolicy", "big".to_string());
+    assert_eq!(result, ContractResponse::RefillPolicyRemoved("big".to_string()));
+
+    // Errors that are not the lack of gas stop the refill of the pool
+    let result = env.call(ADMIN.into(), "SetRefillPolicy", ("tiny".to_string(), RefillPolicy { gas_amount: 1, ..policy }));
+    assert_eq!(result, ContractResponse::RefillPolicySet("tiny".to_string()));
+
+    let result = env.call(ADMIN.into(), "Refill", ());
+    let ContractResponse::Refilled(report) = result else {
+        panic!("Refill report expected: {result:?}");
+    };
+    assert_eq!(report.missing, 0);
+    assert_eq!(report.failed, vec![RefillFailure {
+        pool: "tiny".to_string(),
+        error: GasReservationError::ReservationFailed(RuntimeErrorKind::ReservationBelowMailboxThreshold),
+        not_created: 3
+    }]);
+
+    let result = env.call(ADMIN.into(), "RemoveRefillPolicy", "tiny".to_string());
+    assert_eq!(result, ContractResponse::RefillPolicyRemoved("tiny".to_string()));
+
+    // Must return an error (only admins)
+    let result = env.call(ActorId::from(5), "SetRefillGasMargin", u64::MAX / 2);
+    assert_eq!(result, ContractResponse::Error(GasReservationError::ActionOnlyForAdmins));
+
+    // With a bigger margin the message does not have gas for the new reservations
+    let result = env.call(ADMIN.into(), "SetRefillGasMargin", u64::MAX / 2);
+    assert_eq!(result, ContractResponse::RefillGasMarginSet(u64::MAX / 2));
+
+    let result = env.call(ADMIN.into(), "RefillGasMargin", ());
+    assert_eq!(result, ContractResponse::RefillGasMargin(u64::MAX / 2));
+
+    for _ in 0..3 {
+        env.system.run_next_block();
+    }
+
+    let result = env.call(ADMIN.into(), "Refill", ());
+    assert_eq!(result, ContractResponse::Refilled(RefillReport { created: 0, missing: 3, failed: Vec::new() }));
+}
+
+#[test]
+fn scheduled_calls_are_sent_after_the_delay() {
+    let env = Env::new();
+    let target = ActorId::from(50);
+    let schedule_call = (target, "Target".to_string(), "Ping".to_string(), 7u32.encode(), 10u32, 0u128);
+
+    // Must return an error (there are no reservations in the scheduler pool)
+    let result = env.call(ADMIN.into(), "ScheduleCall", schedule_call.clone());
+    assert_eq!(result, ContractResponse::Error(GasReservationError::NoReservationForJob));
+
+    // Must return an error (the reservation has not enough gas for the wake-up message)
+    let result = env.call(ADMIN.into(), "NewPoolGasReservation", ("scheduler".to_string(), GAS_TO_RESERVE, 100u32, None::<String>));
+    assert_eq!(result, ContractResponse::PoolReservationCreated("scheduler".to_string()));
+
+    let result = env.call(ADMIN.into(), "ScheduleCall", schedule_call.clone());
+    assert_eq!(result, ContractResponse::Error(GasReservationError::NoReservationForJob));
+
+    for _ in 0..2 {
+        let result = env.call(ADMIN.into(), "NewPoolGasReservation", ("scheduler".to_string(), 10 * GAS_TO_RESERVE, 20u32, None::<String>));
+        assert_eq!(result, ContractResponse::PoolReservationCreated("scheduler".to_string()));
+    }
+
+    assert_eq!(env.call(ADMIN.into(), "ScheduleCall", schedule_call.clone()), ContractResponse::JobScheduled(0));
+    assert_eq!(env.call(ADMIN.into(), "ScheduleCall", schedule_call), ContractResponse::JobScheduled(1));
+    assert_eq!(env.call(ADMIN.into(), "CancelJob", 1u64), ContractResponse::JobCancelled(1));
+
+    // Must return an error (only the program sends the wake-up messages)
+    let result = env.call(ADMIN.into(), "DispatchJob", (0u64, 0u64));
+    assert_eq!(result, ContractResponse::Error(GasReservationError::ActionOnlyForProgram));
+
+    // Only the call of the job that was not cancelled is sent
+    let messages = env.messages_to(target, 10);
+    assert_eq!(messages, vec![("Target", "Ping", 7u32).encode()]);
+
+    // The finished jobs keep their final status
+    for (id, status) in [(0u64, JobStatus::Dispatched), (1, JobStatus::Cancelled)] {
+        let ContractResponse::Job(job) = env.call(ADMIN.into(), "Job", id) else {
+            panic!("Job expected");
+        };
+        assert_eq!(job.status, status);
+    }
+
+    let result = env.call(ADMIN.into(), "Job", 2u64);
+    assert_eq!(result, ContractResponse::Error(GasReservationError::JobDoesNotExists));
+
+    assert_eq!(env.call(ADMIN.into(), "ScheduledJobs", ()), ContractResponse::Jobs(Vec::new()));
+
+    // The small reservation is still in the pool
+    let result = env.call(ADMIN.into(), "PoolStatus", "scheduler".to_string());
+    assert_eq!(result, ContractResponse::PoolStatus(PoolStatus { active_reservations: 1, total_gas: GAS_TO_RESERVE }));
+}
+
+#[test]
+fn recurring_jobs_stop_when_the_pool_is_empty() {
+    let env = Env::new();
+    let period = 5u32;
+
+    for _ in 0..2 {
+        let result = env.call(ADMIN.into(), "NewPoolGasReservation", ("scheduler".to_string(), 10 * GAS_TO_RESERVE, 30u32, None::<String>));
+        assert_eq!(result, ContractResponse::PoolReservationCreated("scheduler".to_string()));
+    }
+
+    // Must return an error (the handler can not be a route of the service)
+    for method in ["DispatchJob", "RunRecurringJob"] {
+        let result = env.call(ADMIN.into(), "RegisterRecurringJob", (SERVICE.to_string(), method.to_string(), (0u64, 0u64).encode(), period));
+        assert_eq!(result, ContractResponse::Error(GasReservationError::InvalidJobHandler));
+    }
+
+    // The handler is other service of the program, the first run takes a reservation
+    let handler = ("LeaseCheckService".to_string(), "LeaseAndFail".to_string(), "rewards".encode(), period);
+    let result = env.call(ADMIN.into(), "RegisterRecurringJob", handler);
+    assert_eq!(result, ContractResponse::RecurringJobRegistered(0));
+
+    // The second run takes the last reservation, the third one stops the job
+    let events = env.messages_to(ActorId::zero(), 2 * period);
+    let stopped = (SERVICE, "RecurringJobStopped", 0u64, JobStopReason::ReservationPoolEmpty).encode();
+    assert!(events.contains(&stopped), "The job must be stopped");
+
+    let ContractResponse::JobHealth(health) = env.call(ADMIN.into(), "JobHealth", ()) else {
+        panic!("Job health expected");
+    };
+
+    assert_eq!(health.len(), 1);
+    assert_eq!(health[0], JobHealth {
+        id: 0,
+        status: RecurringJobStatus::Stopped(JobStopReason::ReservationPoolEmpty),
+        runs: 2,
+        failed_runs: 0,
+        last_run: health[0].last_run,
+        next_run: None,
+        reservations_left: 0
+    });
+}
+
+#[test]
+fn cancelled_recurring_jobs_ignore_their_wake_up() {
+    let env = Env::new();
+    let period = 5u32;
+
+    for _ in 0..2 {
+        env.call(ADMIN.into(), "NewPoolGasReservation", ("scheduler".to_string(), 10 * GAS_TO_RESERVE, 30u32, None::<String>));
+    }
+
+    let handler = ("LeaseCheckService".to_string(), "LeaseAndFail".to_string(), "rewards".encode(), period);
+    assert_eq!(env.call(ADMIN.into(), "RegisterRecurringJob", handler), ContractResponse::RecurringJobRegistered(0));
+    assert_eq!(env.call(ADMIN.into(), "CancelJob", 0u64), ContractResponse::JobCancelled(0));
+
+    // Must return an error (the job is already cancelled)
+    let result = env.call(ADMIN.into(), "CancelJob", 0u64);
+    assert_eq!(result, ContractResponse::Error(GasReservationError::JobIsNotScheduled));
+
+    // The wake-up message of the cancelled job does not run it again
+    let events = env.messages_to(ActorId::zero(), 2 * period);
+    assert!(events.is_empty(), "The cancelled job must not run");
+
+    let result = env.call(ADMIN.into(), "PoolStatus", "scheduler".to_string());
+    assert_eq!(result, ContractResponse::PoolStatus(PoolStatus { active_reservations: 1, total_gas: 10 * GAS_TO_RESERVE }));
+
+    let ContractResponse::JobHealth(health) = env.call(ADMIN.into(), "JobHealth", ()) else {
+        panic!("Job health expected");
+    };
+
+    assert_eq!(health.len(), 1);
+    assert_eq!(health[0].status, RecurringJobStatus::Cancelled);
+    assert_eq!(health[0].runs, 0);
+}
+
+#[test]
+fn invalid_reservations_return_typed_errors() {
+    let env = Env::new();
+
+    let result = env.call(ADMIN.into(), "NewGasReservation", (0u64, 10u32, None::<String>));
+    assert_eq!(result, ContractResponse::Error(GasReservationError::ZeroGas));
+
+    assert_eq!(env.new_gas_reservation(0), ContractResponse::Error(GasReservationError::ZeroBlocks));
+
+    let result = env.call(ADMIN.into(), "NewGasReservation", (GAS_TO_RESERVE, 10u32, Some("a".repeat(65))));
+    assert_eq!(result, ContractResponse::Error(GasReservationError::LimitExceeded));
+
+    let result = env.call(ADMIN.into(), "NewPoolGasReservation", ("a".repeat(33), GAS_TO_RESERVE, 10u32, None::<String>));
+    assert_eq!(result, ContractResponse::Error(GasReservationError::LimitExceeded));
+
+    // The message does not have gas to reserve
+    let result = env.call(ADMIN.into(), "NewGasReservation", (u64::MAX / 2, 10u32, None::<String>));
+    assert_eq!(result, ContractResponse::Error(GasReservationError::ReservationFailed(RuntimeErrorKind::NotEnoughGas)));
+
+    assert_eq!(env.reservations_count(), (0, 0));
+}
+
+#[test]
+fn reservation_events_and_low_watermark() {
+    let env = Env::new();
+    let pool = "scheduler".to_string();
+
+    let result = env.call(ADMIN.into(), "SetLowWatermark", (pool.clone(), 2u32));
+    assert_eq!(result, ContractResponse::LowWatermarkSet { pool: pool.clone(), threshold: 2 });
+
+    for _ in 0..2 {
+        env.call(ADMIN.into(), "NewPoolGasReservation", (pool.clone(), 10 * GAS_TO_RESERVE, 20u32, None::<String>));
+        assert_eq!(env.event_names(), vec!["ReservationCreated"]);
+    }
+
+    // The scheduler consumes the first reservation, the pool falls below its low watermark
+    let result = env.call(ADMIN.into(), "ScheduleCall", (ActorId::from(50), "Target".to_string(), "Ping".to_string(), Vec::<u8>::new(), 5u32, 0u128));
+    assert_eq!(result, ContractResponse::JobScheduled(0));
+    assert_eq!(env.event_names(), vec!["ReservationConsumed", "LowWatermark", "JobScheduled"]);
+    assert_eq!(env.events.borrow()[0], (SERVICE, "ReservationConsumed", 0u64, pool.clone()).encode());
+    assert_eq!(env.events.borrow()[1], (SERVICE, "LowWatermark", pool.clone(), 1u32, 2u32).encode());
+
+    let result = env.call(ADMIN.into(), "CancelGasReservation", 1u64);
+    assert!(
+        matches!(result, ContractResponse::GasReservationCancelled { id: 1, .. }),
+        "The reservation must be cancelled"
+    );
+    // The pool is still below its low watermark, the event is not emitted again
+    assert_eq!(env.event_names(), vec!["ReservationUnreserved"]);
+
+    // The pool is back at its low watermark, and falls below it again
+    for _ in 0..2 {
+        env.call(ADMIN.into(), "NewPoolGasReservation", (pool.clone(), 10 * GAS_TO_RESERVE, 20u32, None::<String>));
+    }
+
+    env.call(ADMIN.into(), "CancelGasReservation", 2u64);
+    assert_eq!(env.event_names(), vec!["ReservationUnreserved", "LowWatermark"]);
+
+    assert_eq!(env.new_gas_reservation(2), ContractResponse::ReservationCreated);
+    env.system.run_next_block();
+
+    env.call(ADMIN.into(), "RemoveExpiredGasReservation", ());
+    assert_eq!(env.event_names(), vec!["ExpiredReservationsRemoved"]);
+}
+
+#[test]
+fn consumed_reservations_are_logged() {
+    let env = Env::new();
+    let pool = "scheduler".to_string();
+
+    // Must return an error (capacity over the limit)
+    let result = env.call(ADMIN.into(), "SetConsumptionLogCapacity", 1_001u32);
+    assert_eq!(result, ContractResponse::Error(GasReservationError::LimitExceeded));
+
+    let result = env.call(ADMIN.into(), "SetConsumptionLogCapacity", 10u32);
+    assert_eq!(result, ContractResponse::ConsumptionLogCapacitySet(10));
+
+    env.call(ADMIN.into(), "NewPoolGasReservation", (pool.clone(), 10 * GAS_TO_RESERVE, 20u32, None::<String>));
+    env.call(ADMIN.into(), "ScheduleCall", (ActorId::from(50), "Target".to_string(), "Ping".to_string(), Vec::<u8>::new(), 5u32, 0u128));
+
+    let ContractResponse::ConsumptionLog(page) = env.call(ADMIN.into(), "ConsumptionLog", (0u32, 10u32)) else {
+        panic!("Consumption log expected");
+    };
+
+    assert_eq!(page.len, 1);
+    assert_eq!(page.total_logged, 1);
+    assert_eq!(page.records[0].reservation_id, 0);
+    assert_eq!(page.records[0].pool, pool);
+    assert_eq!(page.records[0].tag, None);
+    assert_eq!(page.records[0].amount, 10 * GAS_TO_RESERVE);
+
+    // The log is disabled
+    env.call(ADMIN.into(), "SetConsumptionLogCapacity", 0u32);
+
+    let ContractResponse::ConsumptionLog(page) = env.call(ADMIN.into(), "ConsumptionLog", (0u32, 10u32)) else {
+        panic!("Consumption log expected");
+    };
+
+    assert!(page.records.is_empty(), "The log must be empty");
+}
+
+#[test]
+fn failed_leases_keep_the_reservation_in_the_queue() {
+    let env = Env::new();
+
+    assert_eq!(env.new_gas_reservation(20), ContractResponse::ReservationCreated);
+    assert_eq!(env.new_gas_reservation(20), ContractResponse::ReservationCreated);
+
+    // The code path that leased the first reservation fails
+    assert_eq!(env.lease_and_fail("default"), Some(0));
+    assert!(env.event_names().is_empty(), "The reservation must not be consumed");
+
+    // The reservation is back at its place in the queue
+    let result = env.call(ADMIN.into(), "ActiveGasReservations", ());
+    let ContractResponse::ActiveGasReservationsData(reservations) = result else {
+        panic!("Active reservations expected");
+    };
+    let ids: Vec<u64> = reservations
+        .iter()
+        .map(|reservation| reservation.id)
+        .collect();
+    assert_eq!(ids, vec![0, 1]);
+
+    let ContractResponse::ConsumptionLog(page) = env.call(ADMIN.into(), "ConsumptionLog", (0u32, 10u32)) else {
+        panic!("Consumption log expected");
+    };
+    assert_eq!(page.total_logged, 0);
+
+    // The same reservation is leased again
+    assert_eq!(env.lease_and_fail("default"), Some(0));
+    assert_eq!(env.lease_and_fail("empty"), None);
+}
+
+#[test]
+fn batch_reservations_stop_at_the_runtime_limits() {
+    let env = Env::new();
+
+    assert_eq!(
+        env.call(ADMIN.into(), "NewGasReservations", (3u32, GAS_TO_RESERVE, 20u32)),
+        ContractResponse::ReservationsCreated(BatchReport { created: 3, stop_reason: BatchStopReason::Completed })
+    );
+    assert_eq!(env.reservations_count(), (3, 0));
+
+    // The message has no gas for the second reservation
+    assert_eq!(
+        env.call(ADMIN.into(), "NewGasReservations", (5u32, 500 * GAS_TO_RESERVE, 20u32)),
+        ContractResponse::ReservationsCreated(BatchReport { created: 1, stop_reason: BatchStopReason::NotEnoughGas })
+    );
+
+    // The program can not have more than 256 reservations
+    assert_eq!(
+        env.call(ADMIN.into(), "NewGasReservations", (300u32, 10_000_000u64, 20u32)),
+        ContractResponse::ReservationsCreated(BatchReport { created: 252, stop_reason: BatchStopReason::ReservationsLimitReached })
+    );
+    assert_eq!(env.reservations_count(), (256, 0));
+
+    // The arguments are checked before creating any reservation
+    assert_eq!(
+        env.call(ADMIN.into(), "NewGasReservations", (3u32, 0u64, 20u32)),
+        ContractResponse::Error(GasReservationError::ZeroGas)
+    );
+}
+
+#[test]
+fn refill_stops_at_the_max_reservations_of_the_runtime() {
+    let env = Env::new();
+    let pool = "rewards".to_string();
+    let policy = RefillPolicy {
+        reservations: 3,
+        gas_amount: 10_000_000,
+        min_remaining_blocks: 5,
+        reservation_blocks: 20
+    };
+
+    env.call(ADMIN.into(), "NewGasReservations", (255u32, 10_000_000u64, 20u32));
+    env.call(ADMIN.into(), "SetRefillPolicy", (pool.clone(), policy));
+
+    // Only one reservation fits under the max reservations of the runtime
+    let result = env.call(ADMIN.into(), "Refill", ());
+    assert_eq!(result, ContractResponse::Refilled(RefillReport {
+        created: 1,
+        missing: 0,
+        failed: vec![RefillFailure {
+            pool,
+            error: GasReservationError::ReservationFailed(RuntimeErrorKind::ReservationsLimitReached),
+            not_created: 2
+        }]
+    }));
+    assert_eq!(env.reservations_count(), (256, 0));
+}
+
+#[test]
+fn only_allowed_accounts_manage_reservations() {
+    let env = Env::new();
+    let stranger = ActorId::from(1);
+
+    // Must return an error (only admins and allowed accounts)
+    let result = env.call(stranger, "NewGasReservation", (GAS_TO_RESERVE, 10u32, None::<String>));
+    assert_eq!(result, ContractResponse::Error(GasReservationError::ActionOnlyForAllowedAccounts));
+
+    let result = env.call(ADMIN.into(), "AllowAccount", stranger);
+    assert_eq!(result, ContractResponse::AccountAllowed(stranger));
+
+    let result = env.call(stranger, "NewGasReservation", (GAS_TO_RESERVE, 10u32, None::<String>));
+    assert_eq!(result, ContractResponse::ReservationCreated);
+
+    // Must return an error (only admins)
+    let result = env.call(stranger, "AddAdmin", stranger);
+    assert_eq!(result, ContractResponse::Error(GasReservationError::ActionOnlyForAdmins));
+}
@@ -0,0 +1,65 @@
+#![no_std]
+
+// # Example program that exposes the gas reservation service
+// Used by the gtest integration tests of the gas reservation service
+
+use sails_rs::prelude::*;
+use gas_reservations_service::{
+    services::GasReservationService,
+    state::GasReservationState
+};
+
+#[derive(Default)]
+pub struct Program;
+
+// # Service that leases reservations like an extending service
+// Used by the tests of the leases
+#[derive(Default)]
+pub struct LeaseCheckService;
+
+#[service]
+impl LeaseCheckService {
+    // Leases the oldest reservation of the pool and fails before using it.
+    // Returns the internal id of the leased reservation
+    pub fn lease_and_fail(&mut self, pool: String) -> Option<u64> {
+        let lease = GasReservationState::lease_pool_reservation(&pool)?;
+        let id = lease.id();
+
+        lease
+            .consume_if(None, |_| Err::<(), ()>(()))
+            .is_err()
+            .then_some(id)
+    }
+}
+
+#[program]
+impl Program {
+    // Program constructor, the source of the init message will be the admin
+    pub fn new() -> Self {
+        GasReservationService::seed();
+
+        Self
+    }
+
+    // Gas reservation service
+    #[export(route = "GasReservationService")]
+    pub fn gas_reservation_svc(&self) -> GasReservationService {
+        GasReservationService::new()
+    }
+
+    // Service used by the tests of the leases
+    #[export(route = "LeaseCheckService")]
+    pub fn lease_check_svc(&self) -> LeaseCheckService {
+        LeaseCheckService
+    }
+}
+
+#[cfg(feature = "wasm-binary")]
+#[cfg(not(target_arch = "wasm32"))]
+pub use code::WASM_BINARY_OPT as WASM_BINARY;
+
+#[cfg(feature = "wasm-binary")]
+#[cfg(not(target_arch = "wasm32"))]
+mod code {
+    include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
+}
@@ -0,0 +1,69 @@
+use sails_rs::{
+    prelude::*,
+    hex
+};
+use blake2::{
+    digest::consts::U32,
+    Blake2b,
+    Digest
+};
+
+// Domain separator of the coded name derivation, it has to change if the
+// derivation changes, so old and new coded names can not collide
+pub const USER_CODED_NAME_DOMAIN: &[u8] = b"vara-keyring-service:user-coded-name:v1";
+
+// # Derives the user coded name (walletless)
+// Canonical derivation that all the clients must use to get the coded name
+// of an user: blake2b-256(domain ++ SCALE((username, secret, program_id))).
+// The SCALE encoding prefixes each string with its length, so the bounds
+// between the username and the secret can not be moved to get the same name.
+// Returns the hash as a "0x" prefixed hex string
+pub fn derive_user_coded_name(username: &str, secret: &str, program_id: ActorId) -> String {
+    let mut hasher = Blake2b::<U32>::new();
+
+    hasher.update(USER_CODED_NAME_DOMAIN);
+    hasher.update((username, secret, program_id).encode());
+
+    let hash = hasher.finalize();
+
+    format!("0x{}", hex::encode(hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derive_same_user_coded_name() {
+        let program_id = ActorId::from([1; 32]);
+
+        let coded_name = derive_user_coded_name("user", "secret", program_id);
+        let temp = derive_user_coded_name("user", "secret", program_id);
+
+        assert_eq!(coded_name, temp, "The derivation must be deterministic");
+        assert_eq!(coded_name.len(), 66, "Must be a 32 bytes hex string");
+        assert!(coded_name.starts_with("0x"), "Must be prefixed with 0x");
+    }
+
+    #[test]
+    fn derive_different_user_coded_names() {
+        let program_id = ActorId::from([1; 32]);
+        let coded_name = derive_user_coded_name("user", "secret", program_id);
+
+        // Different username
+        let temp = derive_user_coded_name("user2", "secret", program_id);
+        assert_ne!(coded_name, temp);
+
+        // Different secret
+        let temp = derive_user_coded_name("user", "secret2", program_id);
+        assert_ne!(coded_name, temp);
+
+        // Different program
+        let temp = derive_user_coded_name("user", "secret", ActorId::from([2; 32]));
+        assert_ne!(coded_name, temp);
+
+        // Moving the bound between username and secret
+        let temp = derive_user_coded_name("us", "ersecret", program_id);
+        assert_ne!(coded_name, temp);
+    }
+}
@@ -0,0 +1,449 @@
+// # Host-side (std) utils to create and unlock keyring accounts
+// Compiled only with the "std" feature, it lets backends and tests create
+// the same locked keyring accounts that the frontends (polkadot-js keyring)
+// create, and the exact `KeyringData` that the service stores.
+//
+// The "encoded" field uses the polkadot-js JSON format (version 3):
+// base64(scrypt salt + scrypt params + nonce + xsalsa20-poly1305(pkcs8 pair))
+
+use std::{
+    string::String,
+    vec::Vec
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use blake2::{Blake2b512, Digest};
+use crypto_secretbox::{
+    aead::{Aead, KeyInit},
+    Nonce,
+    XSalsa20Poly1305
+};
+use sails_rs::ActorId;
+use schnorrkel::{ExpansionMode, Keypair, MiniSecretKey};
+
+use crate::state::KeyringData;
+// Same coded name derivation that the contract uses
+#[cfg(feature = "walletless")]
+pub use crate::coded_name::derive_user_coded_name;
+
+// Vara network ss58 prefix
+pub const VARA_SS58_PREFIX: u16 = 137;
+
+// PKCS8 header and divider used by polkadot-js to encode sr25519 pairs
+const PKCS8_HEADER: [u8; 16] = [48, 83, 2, 1, 1, 48, 5, 6, 3, 43, 101, 112, 4, 34, 4, 32];
+const PKCS8_DIVIDER: [u8; 5] = [161, 35, 3, 33, 0];
+const SECRET_KEY_LENGTH: usize = 64;
+const PUBLIC_KEY_LENGTH: usize = 32;
+const PKCS8_LENGTH: usize = PKCS8_HEADER.len() + SECRET_KEY_LENGTH + PKCS8_DIVIDER.len() + PUBLIC_KEY_LENGTH;
+
+const SCRYPT_SALT_LENGTH: usize = 32;
+const SCRYPT_PARAMS_LENGTH: usize = SCRYPT_SALT_LENGTH + 12;
+const NONCE_LENGTH: usize = 24;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyringUtilsError {
+    InvalidScryptParams,
+    InvalidBase64,
+    InvalidEncodedLength,
+    InvalidPassword,
+    InvalidPkcs8Header,
+    InvalidPkcs8Divider,
+    InvalidSecretKey,
+    InvalidSs58Address,
+    AddressDoesNotMatchKeypair,
+    RandomnessUnavailable
+}
+
+// ## Scrypt params used to derive the encryption key from the password
+// The default values are the ones that polkadot-js uses (and accepts)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScryptParams {
+    pub n: u32,
+    pub p: u32,
+    pub r: u32
+}
+
+impl Default for ScryptParams {
+    fn default() -> Self {
+        Self {
+            n: 1 << 15,
+            p: 1,
+            r: 8
+        }
+    }
+}
+
+// # Unlocked keyring account
+// Contains the sr25519 pair that signs the messages (signless and walletless)
+#[derive(Clone)]
+pub struct KeyringAccount {
+    keypair: Keypair
+}
+
+impl KeyringAccount {
+    // ## Creates a new keyring account with a random seed
+    pub fn generate() -> Result<Self, KeyringUtilsError> {
+        let mut seed = [0u8; 32];
+        getrandom::getrandom(&mut seed)
+            .map_err(|_| KeyringUtilsError::RandomnessUnavailable)?;
+
+        Self::from_seed(seed)
+    }
+
+    // ## Creates the keyring account from a 32 bytes seed (mini secret key)
+    // The seed is expanded the same way as polkadot-js and substrate do it
+    pub fn from_seed(seed: [u8; 32]) -> Result<Self, KeyringUtilsError> {
+        let keypair = MiniSecretKey::from_bytes(&seed)
+            .map_err(|_| KeyringUtilsError::InvalidSecretKey)?
+            .expand_to_keypair(ExpansionMode::Ed25519);
+
+        Ok(Self { keypair })
+    }
+
+    // ## Unlocks the keyring account from the data stored in the contract
+    pub fn unlock(keyring_data: &KeyringData, password: &str) -> Result<Self, KeyringUtilsError> {
+        let keypair = decrypt_keypair(keyring_data.encoded(), password)?;
+        let public_key = decode_ss58_address(keyring_data.address())?;
+
+        if public_key != keypair.public.to_bytes() {
+            return Err(KeyringUtilsError::AddressDoesNotMatchKeypair);
+        }
+
+        Ok(Self { keypair })
+    }
+
+    // ## Locks the keyring account, returning the data to store in the contract
+    pub fn lock(&self, password: &str, ss58_prefix: u16) -> Result<KeyringData, KeyringUtilsError> {
+        self.lock_with_params(password, ss58_prefix, ScryptParams::default())
+    }
+
+    // ## Locks the keyring account with custom scrypt params
+    pub fn lock_with_params(
+        &self,
+        password: &str,
+        ss58_prefix: u16,
+        params: ScryptParams
+    ) -> Result<KeyringData, KeyringUtilsError> {
+        let encoded = encrypt_keypair(&self.keypair, password, params)?;
+
+        Ok(KeyringData::new(self.ss58_address(ss58_prefix), encoded))
+    }
+
+    // ## Keyring address, the address that the contract gets as the message source
+    pub fn keyring_address(&self) -> ActorId {
+        ActorId::from(self.keypair.public.to_bytes())
+    }
+
+    // ## Keyring address in ss58 format (as it is stored in `KeyringData`)
+    pub fn ss58_address(&self, ss58_prefix: u16) -> String {
+        encode_ss58_address(&self.keypair.public.to_bytes(), ss58_prefix)
+    }
+
+    pub fn keypair(&self) -> &Keypair {
+        &self.keypair
+    }
+}
+
+// ## Encrypts the keypair to the polkadot-js "encoded" format
+pub fn encrypt_keypair(
+    keypair: &Keypair,
+    password: &str,
+    params: ScryptParams
+) -> Result<String, KeyringUtilsError> {
+    let mut salt = [0u8; SCRYPT_SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LENGTH];
+    getrandom::getrandom(&mut salt)
+        .map_err(|_| KeyringUtilsError::RandomnessUnavailable)?;
+    getrandom::getrandom(&mut nonce)
+        .map_err(|_| KeyringUtilsError::RandomnessUnavailable)?;
+
+    let key = scrypt_key(password, &salt, params)?;
+
+    let pair_bytes = keypair.to_half_ed25519_bytes();
+    let mut pkcs8 = Vec::with_capacity(PKCS8_LENGTH);
+    pkcs8.extend_from_slice(&PKCS8_HEADER);
+    pkcs8.extend_from_slice(&pair_bytes[..SECRET_KEY_LENGTH]);
+    pkcs8.extend_from_slice(&PKCS8_DIVIDER);
+    pkcs8.extend_from_slice(&pair_bytes[SECRET_KEY_LENGTH..]);
+
+    let encrypted = XSalsa20Poly1305::new(&key.into())
+        .encrypt(&Nonce::from(nonce), pkcs8.as_slice())
+        .map_err(|_| KeyringUtilsError::InvalidSecretKey)?;
+
+    let mut encoded = Vec::with_capacity(SCRYPT_PARAMS_LENGTH + NONCE_LENGTH + encrypted.len());
+    encoded.extend_from_slice(&salt);
+    encoded.extend_from_slice(&params.n.to_le_bytes());
+    encoded.extend_from_slice(&params.p.to_le_bytes());
+    encoded.extend_from_slice(&params.r.to_le_bytes());
+    encoded.extend_from_slice(&nonce);
+    encoded.extend_from_slice(&encrypted);
+
+    Ok(STANDARD.encode(encoded))
+}
+
+// ## Decrypts the polkadot-js "encoded" format, returning the keypair
+pub fn decrypt_keypair(encoded: &str, password: &str) -> Result<Keypair, KeyringUtilsError> {
+    let encoded = STANDARD.decode(encoded)
+        .map_err(|_| KeyringUtilsError::InvalidBase64)?;
+
+    if encoded.len() <= SCRYPT_PARAMS_LENGTH + NONCE_LENGTH {
+        return Err(KeyringUtilsError::InvalidEncodedLength);
+    }
+
+    let (salt, rest) = encoded.split_at(SCRYPT_SALT_LENGTH);
+    let (params, rest) = rest.split_at(SCRYPT_PARAMS_LENGTH - SCRYPT_SALT_LENGTH);
+    let (nonce, encrypted) = rest.split_at(NONCE_LENGTH);
+
+    let read_u32 = |index: usize| {
+        u32::from_le_bytes([params[index], params[index + 1], params[index + 2], params[index + 3]])
+    };
+
+    let params = ScryptParams {
+        n: read_u32(0),
+        p: read_u32(4),
+        r: read_u32(8)
+    };
+
+    let key = scrypt_key(password, salt, params)?;
+    let nonce: [u8; NONCE_LENGTH] = nonce
+        .try_into()
+        .map_err(|_| KeyringUtilsError::InvalidEncodedLength)?;
+
+    let pkcs8 = XSalsa20Poly1305::new(&key.into())
+        .decrypt(&Nonce::from(nonce), encrypted)
+        .map_err(|_| KeyringUtilsError::InvalidPassword)?;
+
+    decode_pkcs8(&pkcs8)
+}
+
+// ## Encodes a public key in ss58 format with the given prefix
+pub fn encode_ss58_address(public_key: &[u8; 32], ss58_prefix: u16) -> String {
+    let mut data = ss58_prefix_bytes(ss58_prefix);
+    data.extend_from_slice(public_key);
+
+    let checksum = ss58_checksum(&data);
+    data.extend_from_slice(&checksum[..2]);
+
+    bs58::encode(data).into_string()
+}
+
+// ## Decodes a ss58 address, returning the public key
+pub fn decode_ss58_address(address: &str) -> Result<[u8; 32], KeyringUtilsError> {
+    let data = bs58::decode(address)
+        .into_vec()
+        .map_err(|_| KeyringUtilsError::InvalidSs58Address)?;
+
+    let prefix_length = match data.first() {
+        Some(0..=63) => 1,
+        Some(64..=127) => 2,
+        _ => return Err(KeyringUtilsError::InvalidSs58Address)
+    };
+
+    if data.len() != prefix_length + PUBLIC_KEY_LENGTH + 2 {
+        return Err(KeyringUtilsError::InvalidSs58Address);
+    }
+
+    let (payload, checksum) = data.split_at(prefix_length + PUBLIC_KEY_LENGTH);
+
+    if ss58_checksum(payload)[..2] != *checksum {
+        return Err(KeyringUtilsError::InvalidSs58Address);
+    }
+
+    let mut public_key = [0u8; PUBLIC_KEY_LENGTH];
+    public_key.copy_from_slice(&payload[prefix_length..]);
+
+    Ok(public_key)
+}
+
+fn decode_pkcs8(pkcs8: &[u8]) -> Result<Keypair, KeyringUtilsError> {
+    if pkcs8.len() != PKCS8_LENGTH {
+        return Err(KeyringUtilsError::InvalidEncodedLength);
+    }
+
+    let (header, rest) = pkcs8.split_at(PKCS8_HEADER.len());
+    let (secret_key, rest) = rest.split_at(SECRET_KEY_LENGTH);
+    let (divider, public_key) = rest.split_at(PKCS8_DIVIDER.len());
+
+    if header != PKCS8_HEADER {
+        return Err(KeyringUtilsError::InvalidPkcs8Header);
+    }
+
+    if divider != PKCS8_DIVIDER {
+        return Err(KeyringUtilsError::InvalidPkcs8Divider);
+    }
+
+    let mut pair_bytes = [0u8; SECRET_KEY_LENGTH + PUBLIC_KEY_LENGTH];
+    pair_bytes[..SECRET_KEY_LENGTH].copy_from_slice(secret_key);
+    pair_bytes[SECRET_KEY_LENGTH..].copy_from_slice(public_key);
+
+    let keypair = Keypair::from_half_ed25519_bytes(&pair_bytes)
+        .map_err(|_| KeyringUtilsError::InvalidSecretKey)?;
+
+    // The public key stored in the pkcs8 must be the one of the secret key
+    if keypair.secret.to_public() != keypair.public {
+        return Err(KeyringUtilsError::InvalidSecretKey);
+    }
+
+    Ok(keypair)
+}
+
+fn scrypt_key(password: &str, salt: &[u8], params: ScryptParams) -> Result<[u8; 32], KeyringUtilsError> {
+    if !params.n.is_power_of_two() || params.n < 2 {
+        return Err(KeyringUtilsError::InvalidScryptParams);
+    }
+
+    let log_n = params.n.trailing_zeros() as u8;
+    let scrypt_params = scrypt::Params::new(log_n, params.r, params.p, 64)
+        .map_err(|_| KeyringUtilsError::InvalidScryptParams)?;
+
+    // polkadot-js derives 64 bytes and uses the first 32 as the secretbox key
+    let mut derived = [0u8; 64];
+    scrypt::scrypt(password.as_bytes(), salt, &scrypt_params, &mut derived)
+        .map_err(|_| KeyringUtilsError::InvalidScryptParams)?;
+
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&derived[..32]);
+
+    Ok(key)
+}
+
+fn ss58_prefix_bytes(ss58_prefix: u16) -> Vec<u8> {
+    let ident = ss58_prefix & 0b0011_1111_1111_1111;
+
+    if ident < 64 {
+        return [ident as u8].to_vec();
+    }
+
+    let first = ((ident & 0b0000_0000_1111_1100) as u8) >> 2 | 0b0100_0000;
+    let second = ((ident >> 8) as u8) | ((ident & 0b0000_0000_0000_0011) as u8) << 6;
+
+    [first, second].to_vec()
+}
+
+fn ss58_checksum(data: &[u8]) -> Vec<u8> {
+    let mut hasher = Blake2b512::new();
+    hasher.update(b"SS58PRE");
+    hasher.update(data);
+
+    hasher.finalize().to_vec()
+}
+
+impl core::fmt::Display for KeyringUtilsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for KeyringUtilsError {}
+
+#[cfg(test)]
+mod tests {
+    use sails_rs::{Decode, Encode};
+
+    use super::*;
+
+    // Lighter scrypt params, the default ones are too slow in debug builds
+    const TEST_PARAMS: ScryptParams = ScryptParams { n: 1 << 8, p: 1, r: 8 };
+    const PASSWORD: &str = "my-secret-password";
+
+    #[test]
+    fn lock_and_unlock_keyring_account() {
+        let account = KeyringAccount::generate().expect("Error while generating the account");
+
+        let keyring_data = account
+            .lock_with_params(PASSWORD, VARA_SS58_PREFIX, TEST_PARAMS)
+            .expect("Error while locking the account");
+
+        assert_eq!(keyring_data.address(), account.ss58_address(VARA_SS58_PREFIX));
+
+        let unlocked = KeyringAccount::unlock(&keyring_data, PASSWORD)
+            .expect("Error while unlocking the account");
+
+        assert_eq!(unlocked.keyring_address(), account.keyring_address());
+        assert_eq!(unlocked.keypair().secret.to_bytes(), account.keypair().secret.to_bytes());
+    }
+
+    #[test]
+    fn fail_unlock_keyring_account() {
+        let account = KeyringAccount::from_seed([7; 32]).expect("Error while creating the account");
+        let other_account = KeyringAccount::from_seed([8; 32]).expect("Error while creating the account");
+
+        let keyring_data = account
+            .lock_with_params(PASSWORD, VARA_SS58_PREFIX, TEST_PARAMS)
+            .expect("Error while locking the account");
+
+        // Must return an error (wrong password)
+        let temp = KeyringAccount::unlock(&keyring_data, "wrong-password");
+        assert_eq!(temp.err(), Some(KeyringUtilsError::InvalidPassword));
+
+        // Must return an error (the address is not the one of the locked pair)
+        let keyring_data = KeyringData::new(
+            other_account.ss58_address(VARA_SS58_PREFIX),
+            String::from(keyring_data.encoded())
+        );
+        let temp = KeyringAccount::unlock(&keyring_data, PASSWORD);
+        assert_eq!(temp.err(), Some(KeyringUtilsError::AddressDoesNotMatchKeypair));
+
+        // Must return an error (invalid encoded data)
+        let temp = decrypt_keypair("not base64!", PASSWORD);
+        assert_eq!(temp.err(), Some(KeyringUtilsError::InvalidBase64));
+
+        let temp = decrypt_keypair("AAAA", PASSWORD);
+        assert_eq!(temp.err(), Some(KeyringUtilsError::InvalidEncodedLength));
+    }
+
+    #[test]
+    fn keyring_data_scale_round_trip() {
+        let account = KeyringAccount::from_seed([1; 32]).expect("Error while creating the account");
+
+        let keyring_data = account
+            .lock_with_params(PASSWORD, VARA_SS58_PREFIX, TEST_PARAMS)
+            .expect("Error while locking the account");
+
+        // Same bytes that the service receives and returns
+        let encoded = keyring_data.encode();
+        let decoded = KeyringData::decode(&mut encoded.as_slice())
+            .expect("Error while decoding keyring data");
+
+        assert_eq!(decoded, keyring_data);
+
+        let unlocked = KeyringAccount::unlock(&decoded, PASSWORD)
+            .expect("Error while unlocking the account");
+
+        assert_eq!(unlocked.keyring_address(), account.keyring_address());
+    }
+
+    #[test]
+    fn lock_with_default_params() {
+        let account = KeyringAccount::from_seed([2; 32]).expect("Error while creating the account");
+
+        let keyring_data = account
+            .lock(PASSWORD, VARA_SS58_PREFIX)
+            .expect("Error while locking the account");
+
+        let unlocked = KeyringAccount::unlock(&keyring_data, PASSWORD)
+            .expect("Error while unlocking the account");
+
+        assert_eq!(unlocked.keyring_address(), account.keyring_address());
+    }
+
+    #[test]
+    fn ss58_address_encoding() {
+        // Public key of the "//Alice" development account
+        let public_key: [u8; 32] = [
+            0xd4, 0x35, 0x93, 0xc7, 0x15, 0xfd, 0xd3, 0x1c, 0x61, 0x14, 0x1a, 0xbd, 0x04, 0xa9, 0x9f, 0xd6,
+            0x82, 0x2c, 0x85, 0x58, 0x85, 0x4c, 0xcd, 0xe3, 0x9a, 0x56, 0x84, 0xe7, 0xa5, 0x6d, 0xa2, 0x7d
+        ];
+
+        let address = encode_ss58_address(&public_key, 42);
+        assert_eq!(address, "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY");
+
+        let vara_address = encode_ss58_address(&public_key, VARA_SS58_PREFIX);
+        assert_eq!(decode_ss58_address(&vara_address), Ok(public_key));
+        assert_eq!(decode_ss58_address(&address), Ok(public_key));
+
+        // Must return an error (invalid checksum)
+        let temp = decode_ss58_address("5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQZ");
+        assert_eq!(temp, Err(KeyringUtilsError::InvalidSs58Address));
+    }
+}
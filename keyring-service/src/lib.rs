@@ -1,6 +1,13 @@
 #![no_std]
 #![allow(clippy::new_without_default)]
 
+#[cfg(feature = "std")]
+extern crate std;
+
 pub mod service_enums;
 pub mod state;
 pub mod services;
+#[cfg(feature = "walletless")]
+pub mod coded_name;
+#[cfg(feature = "std")]
+pub mod keyring_utils;
@@ -1,32 +1,260 @@
 use sails_rs::prelude::*;
 
-use crate::state::KeyringData;
+use crate::state::{
+    KeyringData,
+    KeyringInvariantsReport,
+    NamespaceId,
+    PauseStatus,
+    migration::{
+        BindingsBatch,
+        ImportProgress
+    },
+    sponsorship::SponsoredBudget
+};
+#[cfg(feature = "walletless")]
+use crate::state::recovery::{
+    RecoveryConfig,
+    RecoveryRequest
+};
 
+// The variants of the features are at the end of the enums with fixed indices,
+// so the indices (and the IDL) are the same with any set of features
 #[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
 #[codec(crate = sails_rs::scale_codec)]
 #[scale_info(crate = sails_rs::scale_info)]
 pub enum KeyringError {
     KeyringAddressAlreadyEsists,
-    UserAddressAlreadyExists,
-    UserCodedNameAlreadyExists,
     UserDoesNotHasKeyringAccount,
     KeyringAccountAlreadyExists,
     SessionHasInvalidCredentials,
-    UserAndKeyringAddressAreTheSame
+    ActionOnlyForAdmins,
+    AdminAlreadyExists,
+    AdminDoesNotExists,
+    CanNotRemoveLastAdmin,
+    ActionOnlyForAdminsOrRegistrars,
+    RegistrarAlreadyExists,
+    RegistrarDoesNotExists,
+    NamespaceAlreadyExists,
+    NamespaceDoesNotExists,
+    NamespaceIsNotEmpty,
+    ServicePaused,
+    SeedingIsFinished,
+    ImportIntegrityMismatch,
+    ImportIsIncomplete,
+    ServiceIsNotPaused,
+    BatchTooLarge,
+    KeyringAccountDoesNotExists,
+    InvalidSponsorAmount,
+    NotEnoughSponsorBalance,
+    BudgetOfOtherSponsor,
+    BudgetDoesNotExists,
+    NotEnoughBudget,
+    TopUpExceedsPeriodCap,
+    ValueTransferFailed,
+    #[cfg(feature = "signless")]
+    #[codec(index = 28)]
+    UserAddressAlreadyExists,
+    #[cfg(feature = "signless")]
+    #[codec(index = 29)]
+    UserAndKeyringAddressAreTheSame,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 30)]
+    UserCodedNameAlreadyExists,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 31)]
+    InvalidRecoveryGuardians,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 32)]
+    InvalidRecoveryThreshold,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 33)]
+    ActionOnlyForGuardians,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 34)]
+    RecoveryIsNotConfigured,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 35)]
+    RecoveryIsNotInProgress,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 36)]
+    RecoveryAlreadyInProgress,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 37)]
+    GuardianAlreadyApproved,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 38)]
+    RecoveryThresholdNotReached,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 39)]
+    RecoveryTimelockNotExpired {
+        executable_at: u32,
+        current_block: u32
+    },
+    #[cfg(feature = "walletless")]
+    #[codec(index = 40)]
+    RecoveryRequestExpired,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 41)]
+    RecoveryIsStillValid
 }
 
-#[derive(Encode, Decode, TypeInfo, Clone)]
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
 #[codec(crate = sails_rs::scale_codec)]
 #[scale_info(crate = sails_rs::scale_info)]
 pub enum KeyringEvent {
     KeyringAccountSet,
-    Error(KeyringError)
+    AdminAdded(ActorId),
+    AdminRemoved(ActorId),
+    RegistrarAdded(ActorId),
+    RegistrarRemoved(ActorId),
+    NamespaceAdded(NamespaceId),
+    NamespaceRemoved(NamespaceId),
+    Paused(PauseStatus),
+    Unpaused,
+    BindingsImported(ImportProgress),
+    SeedingFinished,
+    SponsorDeposited {
+        sponsor: ActorId,
+        amount: u128,
+        balance: u128
+    },
+    SponsorWithdrawn {
+        sponsor: ActorId,
+        amount: u128,
+        balance: u128
+    },
+    BudgetAssigned {
+        sponsor: ActorId,
+        keyring_address: ActorId,
+        remaining: u128
+    },
+    BudgetRevoked {
+        sponsor: ActorId,
+        keyring_address: ActorId,
+        returned: u128
+    },
+    ToppedUp {
+        keyring_address: ActorId,
+        amount: u128,
+        remaining: u128
+    },
+    Error(KeyringError),
+    #[cfg(feature = "walletless")]
+    #[codec(index = 17)]
+    KeyringAccountsSet(Vec<Result<(), KeyringError>>),
+    #[cfg(feature = "walletless")]
+    #[codec(index = 18)]
+    RecoveryConfigSet,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 19)]
+    RecoveryConfigRemoved,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 20)]
+    RecoveryInitiated {
+        new_keyring_address: ActorId,
+        executable_at: Option<u32>
+    },
+    #[cfg(feature = "walletless")]
+    #[codec(index = 21)]
+    RecoveryApproved {
+        guardian: ActorId,
+        executable_at: Option<u32>
+    },
+    #[cfg(feature = "walletless")]
+    #[codec(index = 22)]
+    RecoveryCancelled,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 23)]
+    RecoveryAborted,
+    #[cfg(feature = "walletless")]
+    #[codec(index = 24)]
+    RecoveryFinalized(ActorId)
 }
 
-#[derive(Encode, Decode, TypeInfo, Clone)]
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Debug)]
 #[codec(crate = sails_rs::scale_codec)]
 #[scale_info(crate = sails_rs::scale_info)]
 pub enum KeyringQueryEvent {
     KeyringAccountAddress(Option<ActorId>),
     KeyringAccountData(Option<KeyringData>),
-}
\ No newline at end of file
+    InvariantsReport(KeyringInvariantsReport),
+    Admins(Vec<ActorId>),
+    Registrars(Vec<ActorId>),
+    Namespaces(Vec<NamespaceId>),
+    PauseStatus(PauseStatus),
+    ImportProgress(Option<ImportProgress>),
+    BindingsExported(BindingsBatch),
+    SponsorBalance(u128),
+    SponsoredBudget(Option<SponsoredBudget>),
+    Error(KeyringError),
+    #[cfg(feature = "walletless")]
+    #[codec(index = 12)]
+    UserCodedNameIsTaken(bool),
+    #[cfg(feature = "walletless")]
+    #[codec(index = 13)]
+    RecoveryConfig(Option<RecoveryConfig>),
+    #[cfg(feature = "walletless")]
+    #[codec(index = 14)]
+    RecoveryRequest(Option<RecoveryRequest>)
+}
+
+// # Events of the sponsored budgets
+// Emitted (sails events) when the value of the sponsors moves, so off-chain
+// consumers can follow the budgets without polling the queries
+#[sails_rs::event]
+#[derive(Encode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum SponsorshipEvent {
+    SponsorDeposited {
+        sponsor: ActorId,
+        amount: u128,
+        balance: u128
+    },
+    SponsorWithdrawn {
+        sponsor: ActorId,
+        amount: u128,
+        balance: u128
+    },
+    BudgetAssigned {
+        namespace: NamespaceId,
+        sponsor: ActorId,
+        keyring_address: ActorId,
+        remaining: u128
+    },
+    BudgetRevoked {
+        namespace: NamespaceId,
+        sponsor: ActorId,
+        keyring_address: ActorId,
+        returned: u128
+    },
+    ToppedUp {
+        namespace: NamespaceId,
+        keyring_address: ActorId,
+        amount: u128,
+        remaining: u128
+    },
+    // The budget was removed with its namespace, the value went back to the sponsor
+    BudgetRemoved {
+        namespace: NamespaceId,
+        sponsor: ActorId,
+        keyring_address: ActorId,
+        returned: u128
+    }
+}
+
+// # Events of the recovery of the coded names
+#[cfg(feature = "walletless")]
+#[sails_rs::event]
+#[derive(Encode, TypeInfo, Clone, PartialEq, Eq, Debug)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum RecoveryEvent {
+    // The sponsored budget followed the coded name to its new keyring account
+    BudgetMoved {
+        namespace: NamespaceId,
+        old_keyring_address: ActorId,
+        new_keyring_address: ActorId,
+        remaining: u128
+    }
+}
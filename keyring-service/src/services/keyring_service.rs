@@ -1,19 +1,29 @@
 use sails_rs::{
-    prelude::*
+    prelude::*,
+    gstd::msg
 };
 
 use crate::state::{
     KeyringAccounts,
-    KeyringData
+    NamespaceId,
+    migration::{
+        BindingsBatch,
+        ExportCursor,
+        ImportProgress
+    }
 };
 use crate::service_enums::*;
+#[cfg(feature = "signless")]
+use super::signless_keyring_service::SignlessKeyringService;
+#[cfg(feature = "walletless")]
+use super::walletless_keyring_service::WalletlessKeyringService;
 
 #[derive(Clone)]
 pub struct KeyringService();
 
 impl KeyringService {
     // # Init the state of the services
-    // IMPORTANT: this related function need to be called in the program 
+    // IMPORTANT: this related function need to be called in the program
     // constructor, this initializes the state
     pub fn seed() {
         KeyringAccounts::init_state();
@@ -25,111 +35,457 @@ impl KeyringService {
     }
 }
 
-#[service]
+// The signless and walletless commands and queries live in their own services,
+// that are extended here depending on the enabled features ("signless" and
+// "walletless"), so the IDL only contains the enabled methods
+#[cfg_attr(
+    all(feature = "signless", feature = "walletless"),
+    service(extends = [SignlessKeyringService, WalletlessKeyringService], events = SponsorshipEvent)
+)]
+#[cfg_attr(
+    all(feature = "signless", not(feature = "walletless")),
+    service(extends = SignlessKeyringService, events = SponsorshipEvent)
+)]
+#[cfg_attr(
+    all(not(feature = "signless"), feature = "walletless"),
+    service(extends = WalletlessKeyringService, events = SponsorshipEvent)
+)]
+#[cfg_attr(
+    not(any(feature = "signless", feature = "walletless")),
+    service(events = SponsorshipEvent)
+)]
 impl KeyringService {
-    // ## Binds keyring data to an user address (command method - changes states)
-    // Remote call "keyring_address_from_user_address" exposed to external consumenrs
-    // Returns an enum variant (from KeyringEvent) that will be sent as a response to the user
-    // Is treated as a command, meaning that it will change the state (&mut self)
-    // Returns the keyring address from an user address
+    // Remote call "keyring_account_data" exposed to external consumenrs
+    // Returns an enum variant (from KeyringQueryEvent) that will be sent as a response to the user
+    // Is treated as a query, keeping everything unchanged and returning some data. (&self)
+    // Returns the keyring coded account from an keyring address
     #[export]
-    pub fn bind_keyring_data_to_user_address(
-        &mut self,
-        user_address: ActorId,
-        keyring_data: KeyringData
-    ) -> KeyringEvent {
-        let keyring_address = Syscall::message_source();
+    pub fn keyring_account_data(
+        &self,
+        namespace: NamespaceId,
+        keyring_address: ActorId
+    ) -> KeyringQueryEvent {
+        let registry = match KeyringAccounts::state_ref().registry(&namespace) {
+            Ok(registry) => registry,
+            Err(keyring_error) => return KeyringQueryEvent::Error(keyring_error)
+        };
 
-        let result = KeyringAccounts::state_mut()
-            .set_keyring_account_to_user_address(
-                keyring_address, 
-                user_address, 
-                keyring_data
-            );
-        
-        match result {
+        let signless_data = registry
+            .keyring_data_by_keyring_address
+            .get(&keyring_address);
+
+        KeyringQueryEvent::KeyringAccountData(signless_data.cloned())
+    }
+
+    // ## Adds a new admin to the service (command method - changes state)
+    // Only admins can call this method
+    #[export]
+    pub fn add_admin(&mut self, address: ActorId) -> KeyringEvent {
+        let state = KeyringAccounts::state_mut();
+
+        if !state.is_admin(Syscall::message_source()) {
+            return KeyringEvent::Error(KeyringError::ActionOnlyForAdmins);
+        }
+
+        match state.add_admin(address) {
             Err(keyring_error) => KeyringEvent::Error(keyring_error),
-            Ok(_) => KeyringEvent::KeyringAccountSet
+            Ok(_) => KeyringEvent::AdminAdded(address)
         }
     }
 
-    // ## Binds keyring data to an user coded name (command method - changes state)
-    // Remote call "keyring_address_from_user_address" exposed to external consumenrs
-    // Returns an enum variant (from KeyringEvent) that will be sent as a response to the user
-    // Is treated as a command, meaning that it will change the state (&mut self)
-    // Returns the keyring address from an user coded name
+    // ## Removes an admin from the service (command method - changes state)
+    // Only admins can call this method, the last admin can not be removed
     #[export]
-    pub fn bind_keyring_data_to_user_coded_name(
-        &mut self,
-        user_coded_name: String,
-        keyring_data: KeyringData
-    ) -> KeyringEvent {
-        let keyring_address = Syscall::message_source(); //msg::source().into();
+    pub fn remove_admin(&mut self, address: ActorId) -> KeyringEvent {
+        let state = KeyringAccounts::state_mut();
 
-        let result = KeyringAccounts::state_mut()
-            .set_keyring_account_to_user_coded_name(
-                keyring_address, 
-                user_coded_name, 
-                keyring_data
-            );
+        if !state.is_admin(Syscall::message_source()) {
+            return KeyringEvent::Error(KeyringError::ActionOnlyForAdmins);
+        }
 
-        match result {
+        match state.remove_admin(address) {
             Err(keyring_error) => KeyringEvent::Error(keyring_error),
-            Ok(_) => KeyringEvent::KeyringAccountSet
+            Ok(_) => KeyringEvent::AdminRemoved(address)
         }
     }
 
-    // Remote call "keyring_address_from_user_address" exposed to external consumenrs
-    // Returns an enum variant (from KeyringQueryEvent) that will be sent as a response to the user
-    // Is treated as a query, keeping everything unchanged and returning some data. (&self)
-    // Returns the keyring address from an user address
+    // ## Adds a trusted registrar to the service (command method - changes state)
+    // Only admins can call this method, registrars can bind keyring accounts in batches
     #[export]
-    pub fn keyring_address_from_user_address(
-        &self,
-        user_address: ActorId
-    ) -> KeyringQueryEvent {
-        let keyring_address = KeyringAccounts::state_ref()
-            .keyring_accounts_address_by_user_address
-            .get(&user_address);
+    pub fn add_registrar(&mut self, address: ActorId) -> KeyringEvent {
+        let state = KeyringAccounts::state_mut();
 
-        // KeyringQueryEvent::SignlessAccountAddress(keyring_address.copied())
-        KeyringQueryEvent::KeyringAccountAddress(keyring_address.copied())
+        if !state.is_admin(Syscall::message_source()) {
+            return KeyringEvent::Error(KeyringError::ActionOnlyForAdmins);
+        }
+
+        match state.add_registrar(address) {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(_) => KeyringEvent::RegistrarAdded(address)
+        }
     }
-    
-    // Remote call "keyring_address_from_no_wallet_coded_name" exposed to external consumenrs
-    // Returns an enum variant (from KeyringQueryEvent) that will be sent as a response to the user
-    // Is treated as a query, keeping everything unchanged and returning some data. (&self)
-    // Returns the keyring address from an user coded name
+
+    // ## Removes a registrar from the service (command method - changes state)
+    // Only admins can call this method
     #[export]
-    pub fn keyring_address_from_user_coded_name(
-        &self,
-        user_coded_name: String
-    ) -> KeyringQueryEvent {
-        let keyring_address = KeyringAccounts::state_ref()
-            .keyring_accounts_address_by_user_coded_name
-            .get(&user_coded_name);
+    pub fn remove_registrar(&mut self, address: ActorId) -> KeyringEvent {
+        let state = KeyringAccounts::state_mut();
 
-        KeyringQueryEvent::KeyringAccountAddress(keyring_address.copied())
+        if !state.is_admin(Syscall::message_source()) {
+            return KeyringEvent::Error(KeyringError::ActionOnlyForAdmins);
+        }
+
+        match state.remove_registrar(address) {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(_) => KeyringEvent::RegistrarRemoved(address)
+        }
     }
 
-    // Remote call "keyring_account_data" exposed to external consumenrs
-    // Returns an enum variant (from KeyringQueryEvent) that will be sent as a response to the user
-    // Is treated as a query, keeping everything unchanged and returning some data. (&self)
-    // Returns the keyring coded account from an keyring address
+    // ## Adds a new namespace (application) to the service (command method - changes state)
+    // Only admins can call this method
     #[export]
-    pub fn keyring_account_data(
-        &self,
-        keyring_address: ActorId
-    ) -> KeyringQueryEvent {
-        let signless_data = KeyringAccounts::state_ref()
-            .keyring_data_by_keyring_address
-            .get(&keyring_address);
+    pub fn add_namespace(&mut self, namespace: NamespaceId) -> KeyringEvent {
+        let state = KeyringAccounts::state_mut();
+
+        if !state.is_admin(Syscall::message_source()) {
+            return KeyringEvent::Error(KeyringError::ActionOnlyForAdmins);
+        }
+
+        match state.add_namespace(namespace.clone()) {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(_) => KeyringEvent::NamespaceAdded(namespace)
+        }
+    }
+
+    // ## Removes a namespace from the service (command method - changes state)
+    // Only admins can call this method, only empty namespaces can be removed
+    #[export]
+    pub fn remove_namespace(&mut self, namespace: NamespaceId) -> KeyringEvent {
+        let state = KeyringAccounts::state_mut();
+
+        if !state.is_admin(Syscall::message_source()) {
+            return KeyringEvent::Error(KeyringError::ActionOnlyForAdmins);
+        }
 
-        let response = match signless_data {
-            Some(data) => Some(data.clone()),
-            None => None
+        let removed_budgets = match state.remove_namespace(&namespace) {
+            Ok(removed_budgets) => removed_budgets,
+            Err(keyring_error) => return KeyringEvent::Error(keyring_error)
         };
 
-        KeyringQueryEvent::KeyringAccountData(response)
+        for (keyring_address, budget) in removed_budgets {
+            self.emit_sponsorship_event(SponsorshipEvent::BudgetRemoved {
+                namespace: namespace.clone(),
+                sponsor: budget.sponsor,
+                keyring_address,
+                returned: budget.remaining
+            });
+        }
+
+        KeyringEvent::NamespaceRemoved(namespace)
+    }
+
+    // ## Pauses the service (command method - changes state)
+    // Only admins can call this method, while paused the bind commands fail,
+    // if "pause_checks" is true the session validation fails too
+    #[export]
+    pub fn pause(&mut self, pause_checks: bool) -> KeyringEvent {
+        let state = KeyringAccounts::state_mut();
+
+        if !state.is_admin(Syscall::message_source()) {
+            return KeyringEvent::Error(KeyringError::ActionOnlyForAdmins);
+        }
+
+        state.pause(pause_checks);
+
+        KeyringEvent::Paused(state.pause_status)
     }
-}
\ No newline at end of file
+
+    // ## Unpauses the service (command method - changes state)
+    // Only admins can call this method
+    #[export]
+    pub fn unpause(&mut self) -> KeyringEvent {
+        let state = KeyringAccounts::state_mut();
+
+        if !state.is_admin(Syscall::message_source()) {
+            return KeyringEvent::Error(KeyringError::ActionOnlyForAdmins);
+        }
+
+        state.unpause();
+
+        KeyringEvent::Unpaused
+    }
+
+    // ## Imports a batch of bindings exported by an old program (command method - changes state)
+    // Only admins can call this method, during the seeding phase of the program
+    // The batches must be imported in the same order as they were exported
+    // (checked with the count and running hash of the batch)
+    #[export]
+    pub fn import_bindings(&mut self, namespace: NamespaceId, batch: BindingsBatch) -> KeyringEvent {
+        let state = KeyringAccounts::state_mut();
+
+        if !state.is_admin(Syscall::message_source()) {
+            return KeyringEvent::Error(KeyringError::ActionOnlyForAdmins);
+        }
+
+        match state.import_bindings(&namespace, batch) {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(progress) => KeyringEvent::BindingsImported(progress)
+        }
+    }
+
+    // ## Finishes the seeding phase of the program (command method - changes state)
+    // Only admins can call this method, after it no more bindings can be imported
+    // "exported" has the final progress of the export of each namespace (count,
+    // running hash and total of the last batch), the imports must match it
+    #[export]
+    pub fn finish_seeding(&mut self, exported: Vec<(NamespaceId, ImportProgress)>) -> KeyringEvent {
+        let state = KeyringAccounts::state_mut();
+
+        if !state.is_admin(Syscall::message_source()) {
+            return KeyringEvent::Error(KeyringError::ActionOnlyForAdmins);
+        }
+
+        match state.finish_seeding(exported) {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(_) => KeyringEvent::SeedingFinished
+        }
+    }
+
+    // Remote call "export_bindings" exposed to external consumenrs
+    // Returns the page of the bindings of the namespace after the cursor (the
+    // default cursor for the first page), with the count and running hash needed
+    // by "import_bindings" in the new program and the cursor of the next page
+    // Only for admins, while the service is paused
+    #[export]
+    pub fn export_bindings(&self, namespace: NamespaceId, cursor: ExportCursor, limit: u32) -> KeyringQueryEvent {
+        let state = KeyringAccounts::state_ref();
+
+        if !state.is_admin(Syscall::message_source()) {
+            return KeyringQueryEvent::Error(KeyringError::ActionOnlyForAdmins);
+        }
+
+        match state.export_bindings(&namespace, cursor, limit) {
+            Err(keyring_error) => KeyringQueryEvent::Error(keyring_error),
+            Ok(batch) => KeyringQueryEvent::BindingsExported(batch)
+        }
+    }
+
+    // Remote call "import_progress" exposed to external consumenrs
+    // Returns the import progress of the namespace (None if nothing was imported)
+    #[export]
+    pub fn import_progress(&self, namespace: NamespaceId) -> KeyringQueryEvent {
+        let progress = KeyringAccounts::state_ref()
+            .imports
+            .get(&namespace)
+            .copied();
+
+        KeyringQueryEvent::ImportProgress(progress)
+    }
+
+    // ## Deposits the attached value as sponsor balance (command method - changes state)
+    // The balance can be assigned as budgets to the keyring accounts
+    #[export]
+    pub fn deposit_sponsorship(&mut self) -> KeyringEvent {
+        let sponsor = Syscall::message_source();
+        let amount = Syscall::message_value();
+
+        let balance = match KeyringAccounts::state_mut().sponsorship.deposit(sponsor, amount) {
+            Ok(balance) => balance,
+            Err(keyring_error) => return KeyringEvent::Error(keyring_error)
+        };
+
+        self.emit_sponsorship_event(SponsorshipEvent::SponsorDeposited { sponsor, amount, balance });
+
+        KeyringEvent::SponsorDeposited { sponsor, amount, balance }
+    }
+
+    // ## Withdraws value from the sponsor balance (command method - changes state)
+    // Only the value that is not assigned to budgets can be withdrawn, the
+    // balance does not change if the value can not be sent
+    #[export]
+    pub fn withdraw_sponsorship(&mut self, amount: u128) -> KeyringEvent {
+        let sponsor = Syscall::message_source();
+
+        let result = KeyringAccounts::state_mut()
+            .sponsorship
+            .withdraw(sponsor, amount, || send_value(sponsor, amount));
+
+        let balance = match result {
+            Ok(balance) => balance,
+            Err(keyring_error) => return KeyringEvent::Error(keyring_error)
+        };
+
+        self.emit_sponsorship_event(SponsorshipEvent::SponsorWithdrawn { sponsor, amount, balance });
+
+        KeyringEvent::SponsorWithdrawn { sponsor, amount, balance }
+    }
+
+    // ## Assigns a budget to a keyring account (command method - changes state)
+    // The amount is taken from the sponsor balance, the keyring account can request
+    // top-ups of at most "period_cap" each "period_blocks" blocks
+    #[export]
+    pub fn assign_budget(
+        &mut self,
+        namespace: NamespaceId,
+        keyring_address: ActorId,
+        amount: u128,
+        period_cap: u128,
+        period_blocks: u32
+    ) -> KeyringEvent {
+        let sponsor = Syscall::message_source();
+
+        let result = KeyringAccounts::state_mut()
+            .assign_budget(sponsor, &namespace, keyring_address, amount, period_cap, period_blocks);
+
+        let remaining = match result {
+            Ok(remaining) => remaining,
+            Err(keyring_error) => return KeyringEvent::Error(keyring_error)
+        };
+
+        self.emit_sponsorship_event(SponsorshipEvent::BudgetAssigned { namespace, sponsor, keyring_address, remaining });
+
+        KeyringEvent::BudgetAssigned { sponsor, keyring_address, remaining }
+    }
+
+    // ## Revokes the budget of a keyring account (command method - changes state)
+    // Only the sponsor of the budget can call this method, the remaining value
+    // goes back to the sponsor balance
+    #[export]
+    pub fn revoke_budget(&mut self, namespace: NamespaceId, keyring_address: ActorId) -> KeyringEvent {
+        let sponsor = Syscall::message_source();
+
+        let result = KeyringAccounts::state_mut()
+            .sponsorship
+            .revoke_budget(sponsor, &namespace, keyring_address);
+
+        let returned = match result {
+            Ok(returned) => returned,
+            Err(keyring_error) => return KeyringEvent::Error(keyring_error)
+        };
+
+        self.emit_sponsorship_event(SponsorshipEvent::BudgetRevoked { namespace, sponsor, keyring_address, returned });
+
+        KeyringEvent::BudgetRevoked { sponsor, keyring_address, returned }
+    }
+
+    // ## Requests a top-up from the budget (command method - changes state)
+    // Called by the keyring account, the value is sent to the keyring address,
+    // the budget does not change if the value can not be sent
+    #[export]
+    pub fn request_top_up(&mut self, namespace: NamespaceId, amount: u128) -> KeyringEvent {
+        let keyring_address = Syscall::message_source();
+
+        let result = KeyringAccounts::state_mut()
+            .top_up(&namespace, keyring_address, amount, || send_value(keyring_address, amount));
+
+        let remaining = match result {
+            Ok(remaining) => remaining,
+            Err(keyring_error) => return KeyringEvent::Error(keyring_error)
+        };
+
+        self.emit_sponsorship_event(SponsorshipEvent::ToppedUp { namespace, keyring_address, amount, remaining });
+
+        KeyringEvent::ToppedUp { keyring_address, amount, remaining }
+    }
+
+    // Remote call "sponsor_balance" exposed to external consumenrs
+    // Returns the sponsor balance that is not assigned to budgets
+    #[export]
+    pub fn sponsor_balance(&self, sponsor: ActorId) -> KeyringQueryEvent {
+        KeyringQueryEvent::SponsorBalance(KeyringAccounts::state_ref().sponsorship.balance_of(sponsor))
+    }
+
+    // Remote call "sponsored_budget" exposed to external consumenrs
+    // Returns the budget of the keyring account, with its top-ups
+    #[export]
+    pub fn sponsored_budget(&self, namespace: NamespaceId, keyring_address: ActorId) -> KeyringQueryEvent {
+        let budget = KeyringAccounts::state_ref()
+            .sponsorship
+            .budget(&namespace, keyring_address);
+
+        KeyringQueryEvent::SponsoredBudget(budget.cloned())
+    }
+
+    // Remote call "pause_status" exposed to external consumenrs
+    // Returns if the service (and the session validation) is paused
+    #[export]
+    pub fn pause_status(&self) -> KeyringQueryEvent {
+        KeyringQueryEvent::PauseStatus(KeyringAccounts::state_ref().pause_status)
+    }
+
+    // Remote call "namespaces" exposed to external consumenrs
+    // Returns the namespaces (applications) of the service
+    #[export]
+    pub fn namespaces(&self) -> KeyringQueryEvent {
+        KeyringQueryEvent::Namespaces(KeyringAccounts::state_ref().namespaces())
+    }
+
+    // Remote call "admins" exposed to external consumenrs
+    // Returns the admins of the service
+    #[export]
+    pub fn admins(&self) -> KeyringQueryEvent {
+        KeyringQueryEvent::Admins(KeyringAccounts::state_ref().admins.clone())
+    }
+
+    // Remote call "registrars" exposed to external consumenrs
+    // Returns the trusted registrars of the service
+    #[export]
+    pub fn registrars(&self) -> KeyringQueryEvent {
+        KeyringQueryEvent::Registrars(KeyringAccounts::state_ref().registrars.clone())
+    }
+
+    // Remote call "keyring_state_invariants" exposed to external consumenrs
+    // Returns the report of the state invariants of the namespace: orphan keyring data, bindings
+    // without keyring data and keyring addresses bound more than once (only for admins)
+    #[export]
+    pub fn keyring_state_invariants(&self, namespace: NamespaceId) -> KeyringQueryEvent {
+        let state = KeyringAccounts::state_ref();
+
+        if !state.is_admin(Syscall::message_source()) {
+            return KeyringQueryEvent::Error(KeyringError::ActionOnlyForAdmins);
+        }
+
+        match state.registry(&namespace) {
+            Err(keyring_error) => KeyringQueryEvent::Error(keyring_error),
+            Ok(registry) => KeyringQueryEvent::InvariantsReport(registry.validate_invariants())
+        }
+    }
+
+    // Emits an event of the sponsored budgets. Panics if the event can not be
+    // sent, so the changes of the message (and the value sent) are reverted
+    // instead of leaving budgets changes without their event
+    fn emit_sponsorship_event(&self, event: SponsorshipEvent) {
+        self.emit_event(event)
+            .expect("Error while emitting the sponsorship event");
+    }
+}
+
+// Sends value to the given address, without gas so it is transferred directly
+// (not through the mailbox)
+fn send_value(destination: ActorId, amount: u128) -> Result<(), KeyringError> {
+    msg::send_bytes_with_gas(destination, [], 0, amount)
+        .map(|_| ())
+        .map_err(|_| KeyringError::ValueTransferFailed)
+}
+
+// Base services conversion, needed by the "extends" argument of the service
+#[cfg(all(feature = "signless", feature = "walletless"))]
+impl From<KeyringService> for (SignlessKeyringService, WalletlessKeyringService) {
+    fn from(_: KeyringService) -> Self {
+        (SignlessKeyringService::new(), WalletlessKeyringService::new())
+    }
+}
+
+#[cfg(all(feature = "signless", not(feature = "walletless")))]
+impl From<KeyringService> for SignlessKeyringService {
+    fn from(_: KeyringService) -> Self {
+        SignlessKeyringService::new()
+    }
+}
+
+#[cfg(all(not(feature = "signless"), feature = "walletless"))]
+impl From<KeyringService> for WalletlessKeyringService {
+    fn from(_: KeyringService) -> Self {
+        WalletlessKeyringService::new()
+    }
+}
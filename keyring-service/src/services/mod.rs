@@ -1 +1,5 @@
-pub mod keyring_service;
\ No newline at end of file
+pub mod keyring_service;
+#[cfg(feature = "signless")]
+pub mod signless_keyring_service;
+#[cfg(feature = "walletless")]
+pub mod walletless_keyring_service;
\ No newline at end of file
@@ -0,0 +1,78 @@
+use sails_rs::{
+    prelude::*
+};
+
+use crate::state::{
+    KeyringAccounts,
+    KeyringData,
+    NamespaceId
+};
+use crate::service_enums::*;
+
+// # Signless (wallet-bound) part of the keyring service
+// It is extended by `KeyringService` when the "signless" feature is enabled,
+// so its methods are exposed under the same "KeyringService" route
+#[derive(Clone)]
+pub struct SignlessKeyringService();
+
+impl SignlessKeyringService {
+    // Service "Constructor"
+    pub fn new() -> Self {
+        Self()
+    }
+}
+
+#[service]
+impl SignlessKeyringService {
+    // ## Binds keyring data to an user address (command method - changes states)
+    // Remote call "keyring_address_from_user_address" exposed to external consumenrs
+    // Returns an enum variant (from KeyringEvent) that will be sent as a response to the user
+    // Is treated as a command, meaning that it will change the state (&mut self)
+    // Returns the keyring address from an user address
+    // The keyring account is only valid in the given namespace (application)
+    #[export]
+    pub fn bind_keyring_data_to_user_address(
+        &mut self,
+        namespace: NamespaceId,
+        user_address: ActorId,
+        keyring_data: KeyringData
+    ) -> KeyringEvent {
+        let keyring_address = Syscall::message_source();
+
+        let result = KeyringAccounts::state_mut()
+            .set_keyring_account_to_user_address(
+                &namespace,
+                keyring_address,
+                user_address,
+                keyring_data
+            );
+
+        match result {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(_) => KeyringEvent::KeyringAccountSet
+        }
+    }
+
+    // Remote call "keyring_address_from_user_address" exposed to external consumenrs
+    // Returns an enum variant (from KeyringQueryEvent) that will be sent as a response to the user
+    // Is treated as a query, keeping everything unchanged and returning some data. (&self)
+    // Returns the keyring address from an user address
+    #[export]
+    pub fn keyring_address_from_user_address(
+        &self,
+        namespace: NamespaceId,
+        user_address: ActorId
+    ) -> KeyringQueryEvent {
+        let registry = match KeyringAccounts::state_ref().registry(&namespace) {
+            Ok(registry) => registry,
+            Err(keyring_error) => return KeyringQueryEvent::Error(keyring_error)
+        };
+
+        let keyring_address = registry
+            .keyring_accounts_address_by_user_address
+            .get(&user_address);
+
+        // KeyringQueryEvent::SignlessAccountAddress(keyring_address.copied())
+        KeyringQueryEvent::KeyringAccountAddress(keyring_address.copied())
+    }
+}
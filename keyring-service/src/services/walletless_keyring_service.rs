@@ -0,0 +1,348 @@
+use sails_rs::{
+    prelude::*
+};
+
+use crate::state::{
+    KeyringAccounts,
+    KeyringData,
+    NamespaceId,
+    recovery::RecoveryConfig
+};
+use crate::service_enums::*;
+
+// # Walletless (coded name) part of the keyring service
+// It is extended by `KeyringService` when the "walletless" feature is enabled,
+// so its methods are exposed under the same "KeyringService" route
+#[derive(Clone)]
+pub struct WalletlessKeyringService();
+
+impl WalletlessKeyringService {
+    // Service "Constructor"
+    pub fn new() -> Self {
+        Self()
+    }
+}
+
+#[service(events = RecoveryEvent)]
+impl WalletlessKeyringService {
+    // ## Binds keyring data to an user coded name (command method - changes state)
+    // Remote call "keyring_address_from_user_address" exposed to external consumenrs
+    // Returns an enum variant (from KeyringEvent) that will be sent as a response to the user
+    // Is treated as a command, meaning that it will change the state (&mut self)
+    // Returns the keyring address from an user coded name
+    // The keyring account is only valid in the given namespace (application)
+    #[export]
+    pub fn bind_keyring_data_to_user_coded_name(
+        &mut self,
+        namespace: NamespaceId,
+        user_coded_name: String,
+        keyring_data: KeyringData
+    ) -> KeyringEvent {
+        let keyring_address = Syscall::message_source(); //msg::source().into();
+
+        let result = KeyringAccounts::state_mut()
+            .set_keyring_account_to_user_coded_name(
+                &namespace,
+                keyring_address,
+                user_coded_name,
+                keyring_data
+            );
+
+        match result {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(_) => KeyringEvent::KeyringAccountSet
+        }
+    }
+
+    // ## Binds a batch of keyring data to user coded names (command method - changes state)
+    // Only admins and trusted registrars can call this method (onboarding backends)
+    // Each item is (user coded name, keyring address, keyring data), returns the result
+    // of each item, so a failed item does not abort the whole batch
+    #[export]
+    pub fn bind_keyring_data_to_user_coded_names(
+        &mut self,
+        namespace: NamespaceId,
+        bindings: Vec<(String, ActorId, KeyringData)>
+    ) -> KeyringEvent {
+        let state = KeyringAccounts::state_mut();
+        let source = Syscall::message_source();
+
+        if !state.is_admin(source) && !state.is_registrar(source) {
+            return KeyringEvent::Error(KeyringError::ActionOnlyForAdminsOrRegistrars);
+        }
+
+        match state.set_keyring_accounts_to_user_coded_names(&namespace, bindings) {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(results) => KeyringEvent::KeyringAccountsSet(results)
+        }
+    }
+
+    // Remote call "keyring_address_from_no_wallet_coded_name" exposed to external consumenrs
+    // Returns an enum variant (from KeyringQueryEvent) that will be sent as a response to the user
+    // Is treated as a query, keeping everything unchanged and returning some data. (&self)
+    // Returns the keyring address from an user coded name
+    #[export]
+    pub fn keyring_address_from_user_coded_name(
+        &self,
+        namespace: NamespaceId,
+        user_coded_name: String
+    ) -> KeyringQueryEvent {
+        let registry = match KeyringAccounts::state_ref().registry(&namespace) {
+            Ok(registry) => registry,
+            Err(keyring_error) => return KeyringQueryEvent::Error(keyring_error)
+        };
+
+        let keyring_address = registry
+            .keyring_accounts_address_by_user_coded_name
+            .get(&user_coded_name);
+
+        KeyringQueryEvent::KeyringAccountAddress(keyring_address.copied())
+    }
+
+    // Remote call "user_coded_name_is_taken" exposed to external consumenrs
+    // Returns an enum variant (from KeyringQueryEvent) that will be sent as a response to the user
+    // Is treated as a query, keeping everything unchanged and returning some data. (&self)
+    // Returns if the user coded name (derived with "derive_user_coded_name") is already bound
+    #[export]
+    pub fn user_coded_name_is_taken(
+        &self,
+        namespace: NamespaceId,
+        user_coded_name: String
+    ) -> KeyringQueryEvent {
+        let registry = match KeyringAccounts::state_ref().registry(&namespace) {
+            Ok(registry) => registry,
+            Err(keyring_error) => return KeyringQueryEvent::Error(keyring_error)
+        };
+
+        let is_taken = registry.user_coded_name_is_taken(&user_coded_name);
+
+        KeyringQueryEvent::UserCodedNameIsTaken(is_taken)
+    }
+
+    // ## Sets the recovery guardians of an user coded name (command method - changes state)
+    // Only the keyring account bound to the coded name can call this method
+    // The guardians can not be changed while a recovery is in progress
+    #[export]
+    pub fn set_recovery_config(
+        &mut self,
+        namespace: NamespaceId,
+        user_coded_name: String,
+        config: RecoveryConfig
+    ) -> KeyringEvent {
+        let keyring_address = Syscall::message_source();
+
+        let result = KeyringAccounts::state_mut()
+            .bindings_registry_mut(&namespace)
+            .and_then(|registry| {
+                registry.set_recovery_config(keyring_address, user_coded_name, config, Syscall::block_height())
+            });
+
+        match result {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(_) => KeyringEvent::RecoveryConfigSet
+        }
+    }
+
+    // ## Removes the recovery guardians of an user coded name (command method - changes state)
+    // Only the keyring account bound to the coded name can call this method,
+    // the recovery in progress (if any) is cancelled
+    #[export]
+    pub fn remove_recovery_config(
+        &mut self,
+        namespace: NamespaceId,
+        user_coded_name: String
+    ) -> KeyringEvent {
+        let keyring_address = Syscall::message_source();
+
+        let result = KeyringAccounts::state_mut()
+            .registry_mut(&namespace)
+            .and_then(|registry| {
+                registry.remove_recovery_config(keyring_address, user_coded_name)
+            });
+
+        match result {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(_) => KeyringEvent::RecoveryConfigRemoved
+        }
+    }
+
+    // ## Starts the recovery of an user coded name (command method - changes state)
+    // Only guardians can call this method, the call counts as an approval
+    // The new keyring data will be bound to the coded name once the recovery is finalized
+    #[export]
+    pub fn initiate_recovery(
+        &mut self,
+        namespace: NamespaceId,
+        user_coded_name: String,
+        new_keyring_address: ActorId,
+        new_keyring_data: KeyringData
+    ) -> KeyringEvent {
+        let guardian = Syscall::message_source();
+
+        let result = KeyringAccounts::state_mut()
+            .bindings_registry_mut(&namespace)
+            .and_then(|registry| {
+                registry.initiate_recovery(
+                    guardian,
+                    user_coded_name,
+                    new_keyring_address,
+                    new_keyring_data,
+                    Syscall::block_height()
+                )
+            });
+
+        match result {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(executable_at) => KeyringEvent::RecoveryInitiated {
+                new_keyring_address,
+                executable_at
+            }
+        }
+    }
+
+    // ## Approves the recovery in progress of an user coded name (command method - changes state)
+    // Only guardians can call this method, the timelock starts when the threshold is reached
+    #[export]
+    pub fn approve_recovery(
+        &mut self,
+        namespace: NamespaceId,
+        user_coded_name: String
+    ) -> KeyringEvent {
+        let guardian = Syscall::message_source();
+
+        let result = KeyringAccounts::state_mut()
+            .bindings_registry_mut(&namespace)
+            .and_then(|registry| {
+                registry.approve_recovery(guardian, user_coded_name, Syscall::block_height())
+            });
+
+        match result {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(executable_at) => KeyringEvent::RecoveryApproved {
+                guardian,
+                executable_at
+            }
+        }
+    }
+
+    // ## Cancels the recovery in progress of an user coded name (command method - changes state)
+    // Only the keyring account bound to the coded name can call this method
+    // It is available while the service is paused, so the users can stop a malicious recovery
+    #[export]
+    pub fn cancel_recovery(
+        &mut self,
+        namespace: NamespaceId,
+        user_coded_name: String
+    ) -> KeyringEvent {
+        let keyring_address = Syscall::message_source();
+
+        let result = KeyringAccounts::state_mut()
+            .registry_mut(&namespace)
+            .and_then(|registry| {
+                registry.cancel_recovery(keyring_address, user_coded_name)
+            });
+
+        match result {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(_) => KeyringEvent::RecoveryCancelled
+        }
+    }
+
+    // ## Aborts the recovery in progress of an user coded name (command method - changes state)
+    // Only guardians can call this method, if the recovery can not be finalized
+    // anymore (expired, or its new keyring account was bound by other way)
+    // It is available while the service is paused, like "cancel_recovery"
+    #[export]
+    pub fn abort_recovery(
+        &mut self,
+        namespace: NamespaceId,
+        user_coded_name: String
+    ) -> KeyringEvent {
+        let guardian = Syscall::message_source();
+
+        let result = KeyringAccounts::state_mut()
+            .registry_mut(&namespace)
+            .and_then(|registry| {
+                registry.abort_recovery(guardian, user_coded_name, Syscall::block_height())
+            });
+
+        match result {
+            Err(keyring_error) => KeyringEvent::Error(keyring_error),
+            Ok(_) => KeyringEvent::RecoveryAborted
+        }
+    }
+
+    // ## Finalizes the recovery of an user coded name (command method - changes state)
+    // Only the new keyring account can call this method, once the timelock expired
+    // The coded name is rebound to the new keyring account, with the sponsored
+    // budget of the old one
+    #[export]
+    pub fn finalize_recovery(
+        &mut self,
+        namespace: NamespaceId,
+        user_coded_name: String
+    ) -> KeyringEvent {
+        let new_keyring_address = Syscall::message_source();
+
+        let result = KeyringAccounts::state_mut()
+            .finalize_recovery(&namespace, new_keyring_address, user_coded_name);
+
+        let (old_keyring_address, budget) = match result {
+            Ok(finalized) => finalized,
+            Err(keyring_error) => return KeyringEvent::Error(keyring_error)
+        };
+
+        // Panics if the event can not be sent, so the recovery is reverted
+        if let Some(budget) = budget {
+            self.emit_event(RecoveryEvent::BudgetMoved {
+                namespace,
+                old_keyring_address,
+                new_keyring_address,
+                remaining: budget.remaining
+            })
+            .expect("Error while emitting the recovery event");
+        }
+
+        KeyringEvent::RecoveryFinalized(new_keyring_address)
+    }
+
+    // Remote call "recovery_config" exposed to external consumenrs
+    // Returns the recovery guardians of an user coded name (if any)
+    #[export]
+    pub fn recovery_config(
+        &self,
+        namespace: NamespaceId,
+        user_coded_name: String
+    ) -> KeyringQueryEvent {
+        let registry = match KeyringAccounts::state_ref().registry(&namespace) {
+            Ok(registry) => registry,
+            Err(keyring_error) => return KeyringQueryEvent::Error(keyring_error)
+        };
+
+        let config = registry
+            .recovery_config_by_user_coded_name
+            .get(&user_coded_name);
+
+        KeyringQueryEvent::RecoveryConfig(config.cloned())
+    }
+
+    // Remote call "recovery_request" exposed to external consumenrs
+    // Returns the recovery in progress of an user coded name (if any)
+    #[export]
+    pub fn recovery_request(
+        &self,
+        namespace: NamespaceId,
+        user_coded_name: String
+    ) -> KeyringQueryEvent {
+        let registry = match KeyringAccounts::state_ref().registry(&namespace) {
+            Ok(registry) => registry,
+            Err(keyring_error) => return KeyringQueryEvent::Error(keyring_error)
+        };
+
+        let request = registry
+            .recovery_request_by_user_coded_name
+            .get(&user_coded_name);
+
+        KeyringQueryEvent::RecoveryRequest(request.cloned())
+    }
+}
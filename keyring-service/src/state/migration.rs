@@ -0,0 +1,473 @@
+use sails_rs::prelude::*;
+#[cfg(any(feature = "signless", feature = "walletless"))]
+use sails_rs::collections::HashSet;
+#[cfg(any(feature = "signless", feature = "walletless"))]
+use core::ops::Bound;
+use blake2::{
+    digest::consts::U32,
+    Blake2b,
+    Digest
+};
+
+use crate::service_enums::KeyringError;
+#[cfg(any(feature = "signless", feature = "walletless"))]
+use super::KeyringData;
+use super::KeyringRegistry;
+
+// # Binding record of a namespace
+// Keyring account bound to an user, with its keyring data, used to move the
+// bindings to a new program
+#[derive(Encode, Decode, TypeInfo, Clone, Debug, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum BindingRecord {
+    #[cfg(feature = "signless")]
+    #[codec(index = 0)]
+    UserAddress {
+        user_address: ActorId,
+        keyring_address: ActorId,
+        keyring_data: KeyringData
+    },
+    #[cfg(feature = "walletless")]
+    #[codec(index = 1)]
+    UserCodedName {
+        user_coded_name: String,
+        keyring_address: ActorId,
+        keyring_data: KeyringData
+    }
+}
+
+// # Key of a binding record
+// The records are exported sorted by key (user addresses first, then coded names)
+#[derive(Encode, Decode, TypeInfo, Clone, Debug, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum BindingKey {
+    #[cfg(feature = "signless")]
+    #[codec(index = 0)]
+    UserAddress(ActorId),
+    #[cfg(feature = "walletless")]
+    #[codec(index = 1)]
+    UserCodedName(String)
+}
+
+// # Cursor of an export
+// Key of the last record exported and the count and running hash of the
+// records exported up to it, so each page only reads and hashes its own
+// records. The default cursor starts the export
+#[derive(Encode, Decode, TypeInfo, Clone, Debug, Default, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct ExportCursor {
+    pub last_key: Option<BindingKey>,
+    pub exported: ImportProgress,
+}
+
+// # Batch of binding records (export and import)
+// "count" and "running_hash" cover all the records from the first one up to
+// the end of the batch, so a missing, repeated or changed batch is detected
+#[derive(Encode, Decode, TypeInfo, Clone, Debug, Default, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct BindingsBatch {
+    pub records: Vec<BindingRecord>,
+    // Records up to the end of the batch
+    pub count: u32,
+    // Running hash of the records up to the end of the batch
+    pub running_hash: [u8; 32],
+    // Records in the namespace (set by the export)
+    pub total: u32,
+    // Cursor of the next page, None if the batch is the last one
+    pub next: Option<ExportCursor>,
+}
+
+// # Progress of the export or the import of a namespace
+// When the export is finished, "count" is equal to "total", and the import must
+// reach the same values before the seeding phase is finished
+#[derive(Encode, Decode, TypeInfo, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct ImportProgress {
+    // Records exported or imported
+    pub count: u32,
+    // Running hash of the exported or imported records
+    pub running_hash: [u8; 32],
+    // Records in the namespace of the old program (set by the export)
+    pub total: u32,
+}
+
+impl BindingRecord {
+    pub fn key(&self) -> BindingKey {
+        match self {
+            #[cfg(feature = "signless")]
+            Self::UserAddress { user_address, .. } => BindingKey::UserAddress(*user_address),
+            #[cfg(feature = "walletless")]
+            Self::UserCodedName { user_coded_name, .. } => BindingKey::UserCodedName(user_coded_name.clone())
+        }
+    }
+}
+
+impl ImportProgress {
+    // ### Progress after adding the given records
+    // running_hash = blake2b-256(running_hash ++ SCALE(record)) for each record
+    pub fn chain(&self, records: &[BindingRecord]) -> Self {
+        records
+            .iter()
+            .fold(*self, |progress, record| {
+                let mut hasher = Blake2b::<U32>::new();
+
+                hasher.update(progress.running_hash);
+                hasher.update(record.encode());
+
+                Self {
+                    count: progress.count.saturating_add(1),
+                    running_hash: hasher.finalize().into(),
+                    ..progress
+                }
+            })
+    }
+}
+
+// ## Methods to export and import the bindings of a namespace
+impl KeyringRegistry {
+    // ### Page of the export after the cursor, with at most "limit" records
+    // Only the records of the page are read (the bindings are sorted by key),
+    // the cursor of the next page is returned with the batch
+    pub fn export_bindings(&self, cursor: ExportCursor, limit: usize) -> BindingsBatch {
+        let mut records = self.records_after(cursor.last_key.as_ref(), limit.saturating_add(1));
+        let has_more = records.len() > limit;
+
+        records.truncate(limit);
+
+        let total = self.binding_records_count();
+        let exported = ImportProgress { total, ..cursor.exported }.chain(&records);
+        let next = has_more.then(|| ExportCursor {
+            last_key: records.last().map(BindingRecord::key),
+            exported
+        });
+
+        BindingsBatch {
+            records,
+            count: exported.count,
+            running_hash: exported.running_hash,
+            total,
+            next
+        }
+    }
+
+    // ### All binding records of the registry
+    // Sorted by key (user addresses first, then coded names), so the export
+    // is the same in every call
+    pub fn binding_records(&self) -> Vec<BindingRecord> {
+        self.records_after(None, usize::MAX)
+    }
+
+    // ### Up to "limit" binding records after the given key
+    // Bindings without keyring data are skipped (they are reported by "validate_invariants")
+    #[cfg_attr(not(any(feature = "signless", feature = "walletless")), allow(unused_variables))]
+    fn records_after(&self, last_key: Option<&BindingKey>, limit: usize) -> Vec<BindingRecord> {
+        #[cfg_attr(not(any(feature = "signless", feature = "walletless")), allow(unused_mut))]
+        let mut records = Vec::new();
+
+        #[cfg(feature = "signless")]
+        {
+            let start = match last_key {
+                None => Some(Bound::Unbounded),
+                Some(BindingKey::UserAddress(user_address)) => Some(Bound::Excluded(user_address)),
+                #[cfg(feature = "walletless")]
+                Some(BindingKey::UserCodedName(_)) => None
+            };
+
+            if let Some(start) = start {
+                records.extend(self.keyring_accounts_address_by_user_address
+                    .range((start, Bound::Unbounded))
+                    .filter_map(|(user_address, keyring_address)| {
+                        let keyring_data = self.keyring_data_by_keyring_address.get(keyring_address)?;
+
+                        Some(BindingRecord::UserAddress {
+                            user_address: *user_address,
+                            keyring_address: *keyring_address,
+                            keyring_data: keyring_data.clone()
+                        })
+                    })
+                    .take(limit));
+            }
+        }
+
+        #[cfg(feature = "walletless")]
+        {
+            let start = match last_key {
+                Some(BindingKey::UserCodedName(user_coded_name)) => Bound::Excluded(user_coded_name),
+                _ => Bound::Unbounded
+            };
+
+            records.extend(self.keyring_accounts_address_by_user_coded_name
+                .range::<String, _>((start, Bound::Unbounded))
+                .filter_map(|(user_coded_name, keyring_address)| {
+                    let keyring_data = self.keyring_data_by_keyring_address.get(keyring_address)?;
+
+                    Some(BindingRecord::UserCodedName {
+                        user_coded_name: user_coded_name.clone(),
+                        keyring_address: *keyring_address,
+                        keyring_data: keyring_data.clone()
+                    })
+                })
+                .take(limit.saturating_sub(records.len())));
+        }
+
+        records
+    }
+
+    // Binding records of the registry (bindings with keyring data)
+    fn binding_records_count(&self) -> u32 {
+        #[cfg_attr(not(any(feature = "signless", feature = "walletless")), allow(unused_mut))]
+        let mut count = 0;
+
+        #[cfg(feature = "signless")]
+        {
+            count += self.keyring_accounts_address_by_user_address
+                .values()
+                .filter(|keyring_address| self.keyring_data_by_keyring_address.contains_key(*keyring_address))
+                .count();
+        }
+
+        #[cfg(feature = "walletless")]
+        {
+            count += self.keyring_accounts_address_by_user_coded_name
+                .values()
+                .filter(|keyring_address| self.keyring_data_by_keyring_address.contains_key(*keyring_address))
+                .count();
+        }
+
+        count as u32
+    }
+
+    // ### Import a batch of binding records
+    // The batch must continue the given progress (same count and running hash
+    // as the export, and the same total as the previous batches), returns the
+    // new progress. All the records are checked before the first write, so a
+    // failed batch does not change the registry
+    pub fn import_bindings(
+        &mut self,
+        progress: ImportProgress,
+        batch: BindingsBatch
+    ) -> Result<ImportProgress, KeyringError> {
+        if progress.count > 0 && progress.total != batch.total {
+            return Err(KeyringError::ImportIntegrityMismatch);
+        }
+
+        let progress = ImportProgress { total: batch.total, ..progress }.chain(&batch.records);
+
+        if progress.count != batch.count
+            || progress.running_hash != batch.running_hash
+            || progress.count > progress.total
+        {
+            return Err(KeyringError::ImportIntegrityMismatch);
+        }
+
+        #[cfg(any(feature = "signless", feature = "walletless"))]
+        {
+            self.check_import(&batch.records)?;
+
+            for record in batch.records {
+                let (keyring_address, keyring_data) = match record {
+                    #[cfg(feature = "signless")]
+                    BindingRecord::UserAddress { user_address, keyring_address, keyring_data } => {
+                        self.keyring_accounts_address_by_user_address.insert(user_address, keyring_address);
+                        (keyring_address, keyring_data)
+                    },
+                    #[cfg(feature = "walletless")]
+                    BindingRecord::UserCodedName { user_coded_name, keyring_address, keyring_data } => {
+                        self.keyring_accounts_address_by_user_coded_name.insert(user_coded_name, keyring_address);
+                        (keyring_address, keyring_data)
+                    }
+                };
+
+                self.add_keyring_data_to_state(keyring_address, keyring_data);
+            }
+        }
+
+        Ok(progress)
+    }
+
+    // ### Check that every record of the batch can be bound
+    // Same checks as the bind methods, against the registry and the previous
+    // records of the batch
+    #[cfg(any(feature = "signless", feature = "walletless"))]
+    fn check_import(&self, records: &[BindingRecord]) -> Result<(), KeyringError> {
+        let mut keyring_addresses: HashSet<ActorId> = HashSet::new();
+        #[cfg(feature = "signless")]
+        let mut user_addresses: HashSet<ActorId> = HashSet::new();
+        #[cfg(feature = "walletless")]
+        let mut user_coded_names: HashSet<&String> = HashSet::new();
+
+        for record in records {
+            let keyring_address = match record {
+                #[cfg(feature = "signless")]
+                BindingRecord::UserAddress { user_address, keyring_address, .. } => {
+                    if user_address == keyring_address {
+                        return Err(KeyringError::UserAndKeyringAddressAreTheSame);
+                    }
+
+                    if self.keyring_accounts_address_by_user_address.contains_key(user_address)
+                        || !user_addresses.insert(*user_address)
+                    {
+                        return Err(KeyringError::UserAddressAlreadyExists);
+                    }
+
+                    keyring_address
+                },
+                #[cfg(feature = "walletless")]
+                BindingRecord::UserCodedName { user_coded_name, keyring_address, .. } => {
+                    if self.keyring_accounts_address_by_user_coded_name.contains_key(user_coded_name)
+                        || !user_coded_names.insert(user_coded_name)
+                    {
+                        return Err(KeyringError::UserCodedNameAlreadyExists);
+                    }
+
+                    keyring_address
+                }
+            };
+
+            if self.keyring_data_by_keyring_address.contains_key(keyring_address)
+                || !keyring_addresses.insert(*keyring_address)
+            {
+                return Err(KeyringError::KeyringAddressAlreadyEsists);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "signless", feature = "walletless"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_and_import_bindings() {
+        let registry = registry_with_bindings();
+
+        let mut imported = KeyringRegistry::default();
+        let mut progress = ImportProgress::default();
+        let mut cursor = Some(ExportCursor::default());
+        let mut pages = 0;
+
+        while let Some(next) = cursor {
+            let batch = registry.export_bindings(next, 2);
+            assert_eq!(batch.total, 5);
+
+            cursor = batch.next.clone();
+            pages += 1;
+
+            progress = imported
+                .import_bindings(progress, batch)
+                .expect("Error while importing the batch");
+        }
+
+        assert_eq!(pages, 3);
+        assert_eq!(progress.count, 5);
+        assert_eq!(progress.total, 5);
+        assert_eq!(imported.binding_records(), registry.binding_records());
+        assert_eq!(registry.export_bindings(ExportCursor::default(), 5).running_hash, progress.running_hash);
+
+        // The last page is exactly full
+        let batch = registry.export_bindings(ExportCursor::default(), 5);
+        assert_eq!(batch.next, None);
+        assert!(imported.validate_invariants().is_consistent(), "Must be consistent");
+    }
+
+    #[test]
+    fn fail_import_of_corrupted_batches() {
+        let registry = registry_with_bindings();
+        let mut imported = KeyringRegistry::default();
+        let first_batch = registry.export_bindings(ExportCursor::default(), 2);
+        let second_batch = registry.export_bindings(first_batch.next.clone().expect("There are more pages"), 2);
+
+        // Must return an error (the first batch is missing)
+        let temp = imported.import_bindings(ImportProgress::default(), second_batch.clone());
+        assert_eq!(temp, Err(KeyringError::ImportIntegrityMismatch));
+
+        // Must return an error (a record was changed)
+        let mut batch = first_batch.clone();
+        batch.records.swap(0, 1);
+        let temp = imported.import_bindings(ImportProgress::default(), batch);
+        assert_eq!(temp, Err(KeyringError::ImportIntegrityMismatch));
+
+        let progress = imported
+            .import_bindings(ImportProgress::default(), first_batch.clone())
+            .expect("Error while importing the batch");
+
+        // Must return an error (the batch is repeated)
+        let temp = imported.import_bindings(progress, first_batch.clone());
+        assert_eq!(temp, Err(KeyringError::ImportIntegrityMismatch));
+
+        // Must return an error (the total of the export changed)
+        let mut batch = second_batch.clone();
+        batch.total += 1;
+        let temp = imported.import_bindings(progress, batch);
+        assert_eq!(temp, Err(KeyringError::ImportIntegrityMismatch));
+
+        // Must return an error (more records than the total)
+        let mut batch = first_batch.clone();
+        batch.total = 1;
+        let temp = KeyringRegistry::default().import_bindings(ImportProgress::default(), batch);
+        assert_eq!(temp, Err(KeyringError::ImportIntegrityMismatch));
+
+        assert_eq!(imported.binding_records(), first_batch.records);
+    }
+
+    #[test]
+    fn failed_import_does_not_change_the_registry() {
+        let mut imported = registry_with_bindings();
+        let before = imported.binding_records();
+
+        // The second record is already bound in the registry
+        let records = vec![
+            BindingRecord::UserCodedName {
+                user_coded_name: "new-coded-name".to_string(),
+                keyring_address: ActorId::from(200),
+                keyring_data: keyring_data(200)
+            },
+            BindingRecord::UserAddress {
+                user_address: ActorId::from(1),
+                keyring_address: ActorId::from(201),
+                keyring_data: keyring_data(201)
+            }
+        ];
+        let progress = ImportProgress::default().chain(&records);
+        let batch = BindingsBatch {
+            records,
+            count: progress.count,
+            running_hash: progress.running_hash,
+            total: 2,
+            next: None
+        };
+
+        let temp = imported.import_bindings(ImportProgress::default(), batch);
+        assert_eq!(temp, Err(KeyringError::UserAddressAlreadyExists));
+        assert_eq!(imported.binding_records(), before);
+    }
+
+    fn registry_with_bindings() -> KeyringRegistry {
+        let mut registry = KeyringRegistry::default();
+
+        for id in 1..=3u64 {
+            registry
+                .set_keyring_account_to_user_address(ActorId::from(id + 100), ActorId::from(id), keyring_data(id))
+                .expect("Error while binding the keyring account");
+        }
+
+        for id in 4..=5u64 {
+            registry
+                .set_keyring_account_to_user_coded_name(ActorId::from(id + 100), format!("coded-name-{id}"), keyring_data(id))
+                .expect("Error while binding the keyring account");
+        }
+
+        registry
+    }
+
+    fn keyring_data(id: u64) -> KeyringData {
+        KeyringData::new(format!("address-{id}"), "fdnn3200jOIO92Noaa".to_string())
+    }
+}
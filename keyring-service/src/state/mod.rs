@@ -2,21 +2,101 @@
 
 use sails_rs::{
     prelude::*,
-    collections::HashMap
+    collections::{
+        BTreeMap,
+        HashMap
+    }
 };
 
 use crate::service_enums::KeyringError;
 
+// Export and import of the bindings (migration to a new program)
+pub mod migration;
+
+use migration::{
+    BindingsBatch,
+    ExportCursor,
+    ImportProgress
+};
+
+// Sponsored gas budgets of the keyring accounts
+pub mod sponsorship;
+
+use sponsorship::{
+    SponsorLedger,
+    SponsoredBudget
+};
+
+// Guardian-based recovery of the user coded names (walletless)
+#[cfg(feature = "walletless")]
+pub mod recovery;
+
+#[cfg(feature = "walletless")]
+use recovery::{
+    RecoveryConfig,
+    RecoveryRequest
+};
+
 pub static mut KEYRING_SERVICE_STATE: Option<KeyringAccounts> = None;
 
+// Id of the application (namespace) that owns a keyring registry
+pub type NamespaceId = String;
+
+// Max items of the batched commands (batch bindings, import and export pages),
+// so a batch fits in the gas limit of a message
+pub const MAX_BATCH: usize = 100;
+
 // # Struct to manage keyrings account
-// Handles all walletless and signless accounts
+// Handles the keyring registries of all the applications (namespaces),
+// a keyring account bound in one namespace is not valid in the others
 #[derive(Default, Clone)]
 pub struct KeyringAccounts {
-    // Binds the wallet user address with the keyring address (signless)
-    pub keyring_accounts_address_by_user_address: HashMap<ActorId, ActorId>,
-    // Binds the user coded name with the keyring address (walletless)
-    pub keyring_accounts_address_by_user_coded_name: HashMap<String, ActorId>,
+    // Keyring registry of each namespace (managed by the admins)
+    pub registries: HashMap<NamespaceId, KeyringRegistry>,
+    // Admins of the service (admin commands and queries)
+    pub admins: Vec<ActorId>,
+    // Trusted registrars, can bind keyring accounts in batches (onboarding backends)
+    pub registrars: Vec<ActorId>,
+    // Pause switch of the service (set by the admins during incidents)
+    pub pause_status: PauseStatus,
+    // Seeding phase of the program, the bindings of an old program can be
+    // imported until the admins finish it
+    pub seeding: bool,
+    // Progress of the import of each namespace (seeding phase)
+    pub imports: HashMap<NamespaceId, ImportProgress>,
+    // Sponsored gas budgets of the keyring accounts
+    pub sponsorship: SponsorLedger,
+}
+
+// # Pause status of the service
+// While paused, the bind commands fail and, if "checks_paused" is set,
+// the session validation ("check_*" methods) fails too
+#[derive(Encode, Decode, TypeInfo, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct PauseStatus {
+    pub paused: bool,
+    pub checks_paused: bool,
+}
+
+// # Keyring registry of a namespace
+// Handles all walletless and signless accounts of an application
+#[derive(Default, Clone)]
+pub struct KeyringRegistry {
+    // Binds the wallet user address with the keyring address (signless), sorted
+    // so the export reads each page from its cursor
+    #[cfg(feature = "signless")]
+    pub keyring_accounts_address_by_user_address: BTreeMap<ActorId, ActorId>,
+    // Binds the user coded name with the keyring address (walletless), sorted
+    // so the export reads each page from its cursor
+    #[cfg(feature = "walletless")]
+    pub keyring_accounts_address_by_user_coded_name: BTreeMap<String, ActorId>,
+    // Recovery guardians of the user coded names (walletless)
+    #[cfg(feature = "walletless")]
+    pub recovery_config_by_user_coded_name: HashMap<String, RecoveryConfig>,
+    // Recoveries in progress of the user coded names (walletless)
+    #[cfg(feature = "walletless")]
+    pub recovery_request_by_user_coded_name: HashMap<String, RecoveryRequest>,
     // Binds the keyring address with its data (keyring encoded data)
     pub keyring_data_by_keyring_address: HashMap<ActorId, KeyringData>,
 }
@@ -26,8 +106,12 @@ pub struct KeyringAccounts {
 impl KeyringAccounts {
     // ## Related function to init the state
     pub fn init_state() {
+        let mut state = Self::default();
+        state.admins.push(Syscall::message_source());
+        state.seeding = true;
+
         unsafe {
-            KEYRING_SERVICE_STATE = Some(Self::default())
+            KEYRING_SERVICE_STATE = Some(state)
         };
     }
 
@@ -46,9 +130,397 @@ impl KeyringAccounts {
     }
 }
 
-// ## Methods to manage keyring accounts
+// ## Methods to manage the admins
+impl KeyringAccounts {
+    pub fn is_admin(&self, address: ActorId) -> bool {
+        self.admins.contains(&address)
+    }
+
+    pub fn add_admin(&mut self, address: ActorId) -> Result<(), KeyringError> {
+        if self.is_admin(address) {
+            return Err(KeyringError::AdminAlreadyExists);
+        }
+
+        self.admins.push(address);
+
+        Ok(())
+    }
+
+    pub fn remove_admin(&mut self, address: ActorId) -> Result<(), KeyringError> {
+        if !self.is_admin(address) {
+            return Err(KeyringError::AdminDoesNotExists);
+        }
+
+        // The service can not be left without admins
+        if self.admins.len() == 1 {
+            return Err(KeyringError::CanNotRemoveLastAdmin);
+        }
+
+        self.admins.retain(|admin| *admin != address);
+
+        Ok(())
+    }
+}
+
+// ## Methods to manage the registrars
+impl KeyringAccounts {
+    pub fn is_registrar(&self, address: ActorId) -> bool {
+        self.registrars.contains(&address)
+    }
+
+    pub fn add_registrar(&mut self, address: ActorId) -> Result<(), KeyringError> {
+        if self.is_registrar(address) {
+            return Err(KeyringError::RegistrarAlreadyExists);
+        }
+
+        self.registrars.push(address);
+
+        Ok(())
+    }
+
+    pub fn remove_registrar(&mut self, address: ActorId) -> Result<(), KeyringError> {
+        if !self.is_registrar(address) {
+            return Err(KeyringError::RegistrarDoesNotExists);
+        }
+
+        self.registrars.retain(|registrar| *registrar != address);
+
+        Ok(())
+    }
+}
+
+// ## Methods to manage the pause switch
+impl KeyringAccounts {
+    // ### Pause the service
+    // If "pause_checks" is true, the session validation is paused too
+    pub fn pause(&mut self, pause_checks: bool) {
+        self.pause_status = PauseStatus {
+            paused: true,
+            checks_paused: pause_checks
+        };
+    }
+
+    pub fn unpause(&mut self) {
+        self.pause_status = PauseStatus::default();
+    }
+
+    // ### Get the keyring registry of the namespace to change its bindings
+    // Fails while the service is paused
+    pub fn bindings_registry_mut(&mut self, namespace: &NamespaceId) -> Result<&mut KeyringRegistry, KeyringError> {
+        if self.pause_status.paused {
+            return Err(KeyringError::ServicePaused);
+        }
+
+        self.registry_mut(namespace)
+    }
+
+    #[cfg(any(feature = "signless", feature = "walletless"))]
+    fn checks_registry(&self, namespace: &NamespaceId) -> Result<&KeyringRegistry, KeyringError> {
+        if self.pause_status.checks_paused {
+            return Err(KeyringError::ServicePaused);
+        }
+
+        self.registry(namespace)
+    }
+}
+
+// ## Methods to export the bindings to a new program
 impl KeyringAccounts {
+    // ### Page of the export of the namespace after the cursor
+    // At most "MAX_BATCH" records are returned, even with a greater limit. The
+    // service must be paused, so no binding changes between the pages
+    pub fn export_bindings(
+        &self,
+        namespace: &NamespaceId,
+        cursor: ExportCursor,
+        limit: u32
+    ) -> Result<BindingsBatch, KeyringError> {
+        if !self.pause_status.paused {
+            return Err(KeyringError::ServiceIsNotPaused);
+        }
+
+        let limit = (limit as usize).clamp(1, MAX_BATCH);
+
+        Ok(self.registry(namespace)?.export_bindings(cursor, limit))
+    }
+}
+
+// ## Methods to import the bindings of an old program (seeding phase)
+impl KeyringAccounts {
+    // ### Import a batch of binding records (exported by the old program)
+    // The batch is checked against the import progress of the namespace
+    // (count and running hash), a failed batch does not change the registry
+    pub fn import_bindings(
+        &mut self,
+        namespace: &NamespaceId,
+        batch: BindingsBatch
+    ) -> Result<ImportProgress, KeyringError> {
+        if !self.seeding {
+            return Err(KeyringError::SeedingIsFinished);
+        }
+
+        if batch.records.len() > MAX_BATCH {
+            return Err(KeyringError::BatchTooLarge);
+        }
+
+        let progress = self.imports
+            .get(namespace)
+            .copied()
+            .unwrap_or_default();
+
+        let progress = self.registry_mut(namespace)?.import_bindings(progress, batch)?;
+
+        self.imports.insert(namespace.clone(), progress);
+
+        Ok(progress)
+    }
+
+    // ### Finish the seeding phase, no more bindings can be imported
+    // "exported" has the final progress of the export of each namespace (the
+    // last batch of the old program), the imports must match it exactly and
+    // every imported namespace must be in it, so a truncated import can not
+    // be sealed
+    pub fn finish_seeding(&mut self, exported: Vec<(NamespaceId, ImportProgress)>) -> Result<(), KeyringError> {
+        if !self.seeding {
+            return Err(KeyringError::SeedingIsFinished);
+        }
+
+        if exported.len() > MAX_BATCH {
+            return Err(KeyringError::BatchTooLarge);
+        }
+
+        let all_imports_exported = self.imports
+            .keys()
+            .all(|namespace| exported.iter().any(|(exported_namespace, _)| exported_namespace == namespace));
+
+        let imports_completed = exported
+            .iter()
+            .all(|(namespace, progress)| {
+                progress.count == progress.total
+                    && self.imports.get(namespace).copied().unwrap_or_default() == *progress
+            });
+
+        if !all_imports_exported || !imports_completed {
+            return Err(KeyringError::ImportIsIncomplete);
+        }
+
+        self.seeding = false;
+        self.imports.clear();
+
+        Ok(())
+    }
+}
+
+// ## Methods to manage the sponsored budgets
+impl KeyringAccounts {
+    // ### Assign a budget to a keyring account bound in the namespace
+    // Returns the remaining value of the budget
+    pub fn assign_budget(
+        &mut self,
+        sponsor: ActorId,
+        namespace: &NamespaceId,
+        keyring_address: ActorId,
+        amount: u128,
+        period_cap: u128,
+        period_blocks: u32
+    ) -> Result<u128, KeyringError> {
+        self.check_keyring_account_exists(namespace, keyring_address)?;
+
+        self.sponsorship.assign_budget(
+            sponsor,
+            namespace,
+            keyring_address,
+            amount,
+            period_cap,
+            period_blocks
+        )
+    }
+
+    // ### Take a top-up from the budget of the keyring account
+    // Fails while the service is paused or if the keyring account is no longer bound,
+    // "transfer" sends the value (see "SponsorLedger::top_up")
+    // Returns the remaining value of the budget
+    pub fn top_up(
+        &mut self,
+        namespace: &NamespaceId,
+        keyring_address: ActorId,
+        amount: u128,
+        transfer: impl FnOnce() -> Result<(), KeyringError>
+    ) -> Result<u128, KeyringError> {
+        if self.pause_status.paused {
+            return Err(KeyringError::ServicePaused);
+        }
+
+        self.check_keyring_account_exists(namespace, keyring_address)?;
+
+        self.sponsorship.top_up(namespace, keyring_address, amount, Syscall::block_height(), transfer)
+    }
+
+    fn check_keyring_account_exists(&self, namespace: &NamespaceId, keyring_address: ActorId) -> Result<(), KeyringError> {
+        if !self.registry(namespace)?.keyring_data_by_keyring_address.contains_key(&keyring_address) {
+            return Err(KeyringError::KeyringAccountDoesNotExists);
+        }
+
+        Ok(())
+    }
+}
+
+// ## Methods to manage the namespaces
+impl KeyringAccounts {
+    pub fn add_namespace(&mut self, namespace: NamespaceId) -> Result<(), KeyringError> {
+        if self.registries.contains_key(&namespace) {
+            return Err(KeyringError::NamespaceAlreadyExists);
+        }
+
+        self.registries.insert(namespace, KeyringRegistry::default());
+
+        Ok(())
+    }
+
+    // ### Removes a namespace
+    // Only empty namespaces can be removed, so no session is lost. The budgets
+    // left in the namespace are removed and their value goes back to the sponsors
+    // Returns the keyring addresses and the removed budgets
+    pub fn remove_namespace(&mut self, namespace: &NamespaceId) -> Result<Vec<(ActorId, SponsoredBudget)>, KeyringError> {
+        if !self.registry(namespace)?.is_empty() {
+            return Err(KeyringError::NamespaceIsNotEmpty);
+        }
+
+        self.registries.remove(namespace);
+
+        Ok(self.sponsorship.clear_namespace(namespace))
+    }
+
+    // ### Sorted list of the namespaces
+    pub fn namespaces(&self) -> Vec<NamespaceId> {
+        let mut namespaces: Vec<NamespaceId> = self.registries
+            .keys()
+            .cloned()
+            .collect();
+
+        namespaces.sort();
+
+        namespaces
+    }
+
+    // ### Get the keyring registry of the namespace
+    pub fn registry(&self, namespace: &NamespaceId) -> Result<&KeyringRegistry, KeyringError> {
+        self.registries
+            .get(namespace)
+            .ok_or(KeyringError::NamespaceDoesNotExists)
+    }
+
+    // ### Get the keyring registry of the namespace as mut
+    pub fn registry_mut(&mut self, namespace: &NamespaceId) -> Result<&mut KeyringRegistry, KeyringError> {
+        self.registries
+            .get_mut(namespace)
+            .ok_or(KeyringError::NamespaceDoesNotExists)
+    }
+
+    // ### Verify that the keyring address is linked to the user's address in the namespace
+    #[cfg(feature = "signless")]
+    pub fn check_keyring_address_by_user_address(
+        &self,
+        namespace: &NamespaceId,
+        keyring_address: ActorId,
+        user_address: ActorId,
+    ) -> Result<(), KeyringError> {
+        self.checks_registry(namespace)?
+            .check_keyring_address_by_user_address(keyring_address, user_address)
+    }
+
+    // ### Verify that the keyring address is linked to the user's coded name in the namespace
+    #[cfg(feature = "walletless")]
+    pub fn check_keyring_address_by_user_coded_name(
+        &self,
+        namespace: &NamespaceId,
+        keyring_address: ActorId,
+        user_coded_name: String
+    ) -> Result<(), KeyringError> {
+        self.checks_registry(namespace)?
+            .check_keyring_address_by_user_coded_name(keyring_address, user_coded_name)
+    }
+
+    // ### Store the keyring data in the namespace
+    // Store and bind the given keyring data with the user's address
+    #[cfg(feature = "signless")]
+    pub fn set_keyring_account_to_user_address(
+        &mut self,
+        namespace: &NamespaceId,
+        keyring_address: ActorId,
+        user_address: ActorId,
+        keyring_data: KeyringData
+    ) -> Result<(), KeyringError> {
+        self.bindings_registry_mut(namespace)?
+            .set_keyring_account_to_user_address(keyring_address, user_address, keyring_data)
+    }
+
+    // ### Store the keyring data in the namespace
+    // Store and bind the given keyring data with the user's coded name
+    #[cfg(feature = "walletless")]
+    pub fn set_keyring_account_to_user_coded_name(
+        &mut self,
+        namespace: &NamespaceId,
+        keyring_address: ActorId,
+        user_coded_name: String,
+        keyring_data: KeyringData
+    ) -> Result<(), KeyringError> {
+        self.bindings_registry_mut(namespace)?
+            .set_keyring_account_to_user_coded_name(keyring_address, user_coded_name, keyring_data)
+    }
+
+    // ### Store a batch of keyring data in the namespace
+    // Store and bind each keyring data with its user's coded name, returns the
+    // result of each item (a failed item does not abort the batch). The batch
+    // can not have more than "MAX_BATCH" items
+    #[cfg(feature = "walletless")]
+    pub fn set_keyring_accounts_to_user_coded_names(
+        &mut self,
+        namespace: &NamespaceId,
+        bindings: Vec<(String, ActorId, KeyringData)>
+    ) -> Result<Vec<Result<(), KeyringError>>, KeyringError> {
+        if bindings.len() > MAX_BATCH {
+            return Err(KeyringError::BatchTooLarge);
+        }
+
+        let registry = self.bindings_registry_mut(namespace)?;
+
+        let results = bindings
+            .into_iter()
+            .map(|(user_coded_name, keyring_address, keyring_data)| {
+                registry.set_keyring_account_to_user_coded_name(keyring_address, user_coded_name, keyring_data)
+            })
+            .collect();
+
+        Ok(results)
+    }
+
+    // ### Finalize the recovery of the user coded name
+    // The coded name is rebound to the new keyring account and the sponsored
+    // budget of the old keyring account (if any) is moved to the new one
+    // Returns the old keyring address and the moved budget
+    #[cfg(feature = "walletless")]
+    pub fn finalize_recovery(
+        &mut self,
+        namespace: &NamespaceId,
+        new_keyring_address: ActorId,
+        user_coded_name: String
+    ) -> Result<(ActorId, Option<SponsoredBudget>), KeyringError> {
+        let old_keyring_address = self.bindings_registry_mut(namespace)?
+            .finalize_recovery(new_keyring_address, user_coded_name, Syscall::block_height())?;
+
+        let budget = self.sponsorship
+            .move_budget(namespace, old_keyring_address, new_keyring_address)
+            .cloned();
+
+        Ok((old_keyring_address, budget))
+    }
+}
+
+// ## Methods to manage keyring accounts
+impl KeyringRegistry {
     // ### Verify that the keyring address is linked to the user's address
+    #[cfg(feature = "signless")]
     pub fn check_keyring_address_by_user_address(
         &self,
         keyring_address: ActorId,
@@ -76,6 +548,7 @@ impl KeyringAccounts {
     }
 
     // ### Verify that the keyring address is linked to the user's coded name
+    #[cfg(feature = "walletless")]
     pub fn check_keyring_address_by_user_coded_name(
         &self,
         keyring_address: ActorId,
@@ -96,8 +569,16 @@ impl KeyringAccounts {
         Ok(())
     }
 
+    // ### Check if the user's coded name is already bound to a keyring account
+    #[cfg(feature = "walletless")]
+    pub fn user_coded_name_is_taken(&self, user_coded_name: &str) -> bool {
+        self.keyring_accounts_address_by_user_coded_name
+            .contains_key(user_coded_name)
+    }
+
     // ### Store the keyring data
     // Store and bind the given keyring data with the user's address
+    #[cfg(feature = "signless")]
     pub fn set_keyring_account_to_user_address(
         &mut self, 
         keyring_address: ActorId,
@@ -135,6 +616,7 @@ impl KeyringAccounts {
 
     // ### Store the keyring data
     // Store and bind the given keyring data with the user's address
+    #[cfg(feature = "walletless")]
     pub fn set_keyring_account_to_user_coded_name(
         &mut self,
         keyring_address: ActorId,
@@ -164,14 +646,95 @@ impl KeyringAccounts {
         Ok(())
     }
 
+    // ### Validate the consistency between the maps of the state
+    // Every keyring data needs exactly one owner (user address or coded name),
+    // and every bound keyring address needs its keyring data
+    pub fn validate_invariants(&self) -> KeyringInvariantsReport {
+        let mut report = KeyringInvariantsReport::default();
+        #[cfg_attr(not(any(feature = "signless", feature = "walletless")), allow(unused_mut))]
+        let mut owners_by_keyring_address: HashMap<ActorId, u32> = HashMap::new();
+
+        #[cfg(feature = "signless")]
+        for (user_address, keyring_address) in self.keyring_accounts_address_by_user_address.iter() {
+            if user_address == keyring_address {
+                report.users_bound_to_themselves.push(*user_address);
+            }
+
+            *owners_by_keyring_address.entry(*keyring_address).or_default() += 1;
+        }
+
+        #[cfg(feature = "walletless")]
+        for keyring_address in self.keyring_accounts_address_by_user_coded_name.values() {
+            *owners_by_keyring_address.entry(*keyring_address).or_default() += 1;
+        }
+
+        for (keyring_address, owners) in owners_by_keyring_address.iter() {
+            if !self.keyring_data_by_keyring_address.contains_key(keyring_address) {
+                report.bindings_without_keyring_data.push(*keyring_address);
+            }
+
+            if *owners > 1 {
+                report.keyring_addresses_bound_more_than_once.push(*keyring_address);
+            }
+        }
+
+        for keyring_address in self.keyring_data_by_keyring_address.keys() {
+            if !owners_by_keyring_address.contains_key(keyring_address) {
+                report.keyring_data_without_owner.push(*keyring_address);
+            }
+        }
+
+        report.sort();
+
+        report
+    }
+
+    // ### Check if the registry has no keyring accounts
+    pub fn is_empty(&self) -> bool {
+        self.keyring_data_by_keyring_address.is_empty()
+    }
+
+    #[cfg(any(feature = "signless", feature = "walletless"))]
     fn add_keyring_data_to_state(&mut self, keyring_address: ActorId, keyring_data: KeyringData) {
         self.keyring_data_by_keyring_address
             .insert(keyring_address, keyring_data);
     }
 }
 
+// # Report of the state invariants (see "validate_invariants")
+// All addresses are sorted, an empty report means a consistent state
+#[derive(Encode, Decode, TypeInfo, Clone, Default, Debug, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct KeyringInvariantsReport {
+    // Keyring addresses with keyring data but without user address or coded name
+    pub keyring_data_without_owner: Vec<ActorId>,
+    // Keyring addresses bound to an user address or coded name without keyring data
+    pub bindings_without_keyring_data: Vec<ActorId>,
+    // Keyring addresses bound to more than one user address or coded name
+    pub keyring_addresses_bound_more_than_once: Vec<ActorId>,
+    // User addresses bound to themselves as keyring address
+    pub users_bound_to_themselves: Vec<ActorId>,
+}
+
+impl KeyringInvariantsReport {
+    pub fn is_consistent(&self) -> bool {
+        self.keyring_data_without_owner.is_empty()
+            && self.bindings_without_keyring_data.is_empty()
+            && self.keyring_addresses_bound_more_than_once.is_empty()
+            && self.users_bound_to_themselves.is_empty()
+    }
+
+    fn sort(&mut self) {
+        self.keyring_data_without_owner.sort();
+        self.bindings_without_keyring_data.sort();
+        self.keyring_addresses_bound_more_than_once.sort();
+        self.users_bound_to_themselves.sort();
+    }
+}
+
 // # Struct to store the locked keyring data
-#[derive(Encode, Decode, TypeInfo, Clone, Default)]
+#[derive(Encode, Decode, TypeInfo, Clone, Default, Debug, PartialEq, Eq)]
 #[codec(crate = sails_rs::scale_codec)]
 #[scale_info(crate = sails_rs::scale_info)]
 pub struct KeyringData {
@@ -179,16 +742,36 @@ pub struct KeyringData {
     encoded: String,
 }
 
+impl KeyringData {
+    pub fn new(address: String, encoded: String) -> Self {
+        Self {
+            address,
+            encoded
+        }
+    }
+
+    // ### Keyring address (ss58 format)
+    pub fn address(&self) -> &str {
+        &self.address
+    }
 
-#[cfg(test)]
+    // ### Locked keyring account (polkadot-js "encoded" format)
+    pub fn encoded(&self) -> &str {
+        &self.encoded
+    }
+}
+
+
+#[cfg(all(test, any(feature = "signless", feature = "walletless")))]
 mod tests {
     use core::str::FromStr;
 
     use super::*;
 
+    #[cfg(feature = "signless")]
     #[test]
     fn store_keyring_data_with_user_address() {
-        let mut accounts = KeyringAccounts::default();
+        let mut accounts = KeyringRegistry::default();
         let user_address = user_address();
         let keyring_address = keyring_address();
         let keyring_data = keyring_data();
@@ -231,16 +814,21 @@ mod tests {
     
     }
 
+    #[cfg(feature = "walletless")]
     #[test]
     fn store_keyring_data_with_user_coded_name() {
-        let mut accounts = KeyringAccounts::default();
+        let mut accounts = KeyringRegistry::default();
         let user_coded_name = "jsou3305bnsodheiJJCF9nc".to_string();
         let keyring_address = keyring_address();
         let keyring_data = keyring_data();
 
+        assert!(!accounts.user_coded_name_is_taken(&user_coded_name), "User coded name must be free");
+
         // Add the keyring data to the accounts
         let temp = accounts.set_keyring_account_to_user_coded_name(keyring_address, user_coded_name.clone(), keyring_data);
 
+        assert!(accounts.user_coded_name_is_taken(&user_coded_name), "User coded name must be taken");
+
         // check if it returns an error
         if let Err(error) = temp {
             let error_msg = format!("Give an error with: {:?}", error);
@@ -275,9 +863,10 @@ mod tests {
         assert!(temp.is_ok(), "Must be Ok!");
     }
 
+    #[cfg(feature = "signless")]
     #[test]
     fn fail_store_keyring_data_with_user_address() {
-        let mut accounts = KeyringAccounts::default();
+        let mut accounts = KeyringRegistry::default();
         let user_address = user_address();
         let extra_address = extra_address();
         let keyring_address = keyring_address();
@@ -368,9 +957,10 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "walletless")]
     #[test]
     fn fail_store_keyring_data_with_user_coded_name() {
-        let mut accounts = KeyringAccounts::default();
+        let mut accounts = KeyringRegistry::default();
         let user_coded_name = "jsou3305bnsodheiJJCF9nc".to_string();
         let keyring_address = keyring_address();
         let keyring_data = keyring_data();
@@ -447,6 +1037,295 @@ mod tests {
         }
     }
 
+    #[test]
+    fn validate_invariants_of_corrupted_state() {
+        let mut accounts = KeyringRegistry::default();
+        let keyring_address = keyring_address();
+        let extra_address = extra_address();
+
+        // An empty state is consistent
+        assert!(accounts.validate_invariants().is_consistent(), "Must be consistent");
+
+        // Keyring data without owner
+        accounts.keyring_data_by_keyring_address.insert(keyring_address, keyring_data());
+
+        let report = accounts.validate_invariants();
+        assert!(!report.is_consistent(), "Must not be consistent");
+        assert_eq!(report.keyring_data_without_owner, vec![keyring_address]);
+
+        // Binding without keyring data
+        accounts.keyring_data_by_keyring_address.clear();
+        #[cfg(feature = "walletless")]
+        accounts.keyring_accounts_address_by_user_coded_name.insert("coded".to_string(), extra_address);
+        #[cfg(not(feature = "walletless"))]
+        accounts.keyring_accounts_address_by_user_address.insert(user_address(), extra_address);
+
+        let report = accounts.validate_invariants();
+        assert_eq!(report.bindings_without_keyring_data, vec![extra_address]);
+        assert!(report.keyring_data_without_owner.is_empty(), "Must be empty");
+
+        // Keyring address bound twice
+        accounts.keyring_data_by_keyring_address.insert(extra_address, keyring_data());
+        #[cfg(feature = "walletless")]
+        accounts.keyring_accounts_address_by_user_coded_name.insert("coded2".to_string(), extra_address);
+        #[cfg(not(feature = "walletless"))]
+        accounts.keyring_accounts_address_by_user_address.insert(keyring_address, extra_address);
+
+        let report = accounts.validate_invariants();
+        assert_eq!(report.keyring_addresses_bound_more_than_once, vec![extra_address]);
+        assert!(report.bindings_without_keyring_data.is_empty(), "Must be empty");
+    }
+
+    #[test]
+    fn manage_admins() {
+        let mut accounts = KeyringAccounts::default();
+        let admin = keyring_address();
+        let extra_admin = extra_address();
+
+        accounts.admins.push(admin);
+
+        // Must return an error (admin already exists)
+        assert_eq!(accounts.add_admin(admin), Err(KeyringError::AdminAlreadyExists));
+
+        // Must return an error (can not remove the last admin)
+        assert_eq!(accounts.remove_admin(admin), Err(KeyringError::CanNotRemoveLastAdmin));
+
+        assert!(accounts.add_admin(extra_admin).is_ok(), "Must be Ok");
+        assert!(accounts.is_admin(extra_admin), "Must be admin");
+
+        assert!(accounts.remove_admin(admin).is_ok(), "Must be Ok");
+        assert!(!accounts.is_admin(admin), "Must not be admin");
+
+        // Must return an error (admin does not exists)
+        assert_eq!(accounts.remove_admin(admin), Err(KeyringError::AdminDoesNotExists));
+
+        assert!(accounts.add_registrar(admin).is_ok(), "Must be Ok");
+        assert!(accounts.is_registrar(admin), "Must be registrar");
+
+        // Must return an error (registrar already exists)
+        assert_eq!(accounts.add_registrar(admin), Err(KeyringError::RegistrarAlreadyExists));
+
+        assert!(accounts.remove_registrar(admin).is_ok(), "Must be Ok");
+
+        // Must return an error (registrar does not exists)
+        assert_eq!(accounts.remove_registrar(admin), Err(KeyringError::RegistrarDoesNotExists));
+    }
+
+    #[test]
+    #[cfg(feature = "walletless")]
+    fn bind_keyring_accounts_in_batch() {
+        let mut accounts = KeyringAccounts::default();
+        let namespace = "app".to_string();
+
+        assert!(accounts.add_namespace(namespace.clone()).is_ok(), "Must be Ok");
+
+        let bindings = vec![
+            ("coded-a".to_string(), keyring_address(), keyring_data()),
+            // Must fail (coded name already exists)
+            ("coded-a".to_string(), extra_address(), keyring_data()),
+            ("coded-b".to_string(), extra_address(), keyring_data())
+        ];
+
+        let temp = accounts.set_keyring_accounts_to_user_coded_names(&namespace, bindings);
+        assert_eq!(temp, Ok(vec![Ok(()), Err(KeyringError::UserCodedNameAlreadyExists), Ok(())]));
+
+        let registry = accounts.registry(&namespace).expect("Namespace must exist");
+        assert!(registry.check_keyring_address_by_user_coded_name(extra_address(), "coded-b".to_string()).is_ok(), "Must be Ok");
+        assert!(registry.validate_invariants().is_consistent(), "Must be consistent");
+
+        // Must return an error (the namespace does not exists)
+        let temp = accounts.set_keyring_accounts_to_user_coded_names(&"other".to_string(), vec![]);
+        assert_eq!(temp, Err(KeyringError::NamespaceDoesNotExists));
+
+        // Must return an error (the batch is too large), nothing is bound
+        let bindings = (0..=MAX_BATCH as u64)
+            .map(|id| (format!("coded-{id}"), ActorId::from(id + 1_000), keyring_data()))
+            .collect();
+
+        let temp = accounts.set_keyring_accounts_to_user_coded_names(&namespace, bindings);
+        assert_eq!(temp, Err(KeyringError::BatchTooLarge));
+
+        let registry = accounts.registry(&namespace).expect("Namespace must exist");
+        assert!(!registry.user_coded_name_is_taken("coded-0"), "Must not be bound");
+    }
+
+    #[test]
+    fn pause_service() {
+        let mut accounts = KeyringAccounts::default();
+        let namespace = "app".to_string();
+
+        assert!(accounts.add_namespace(namespace.clone()).is_ok(), "Must be Ok");
+
+        #[cfg(feature = "walletless")]
+        let temp = accounts.set_keyring_account_to_user_coded_name(&namespace, keyring_address(), "coded".to_string(), keyring_data());
+        #[cfg(not(feature = "walletless"))]
+        let temp = accounts.set_keyring_account_to_user_address(&namespace, keyring_address(), user_address(), keyring_data());
+        assert!(temp.is_ok(), "Must be Ok");
+
+        accounts.pause(false);
+
+        // Must return an error (the service is paused)
+        #[cfg(feature = "walletless")]
+        let temp = accounts.set_keyring_account_to_user_coded_name(&namespace, extra_address(), "extra".to_string(), keyring_data());
+        #[cfg(not(feature = "walletless"))]
+        let temp = accounts.set_keyring_account_to_user_address(&namespace, extra_address(), extra_address(), keyring_data());
+        assert_eq!(temp, Err(KeyringError::ServicePaused));
+
+        // The session validation is not paused
+        #[cfg(feature = "walletless")]
+        let temp = accounts.check_keyring_address_by_user_coded_name(&namespace, keyring_address(), "coded".to_string());
+        #[cfg(not(feature = "walletless"))]
+        let temp = accounts.check_keyring_address_by_user_address(&namespace, keyring_address(), user_address());
+        assert!(temp.is_ok(), "Must be Ok");
+
+        accounts.pause(true);
+
+        // Must return an error (the session validation is paused)
+        #[cfg(feature = "walletless")]
+        let temp = accounts.check_keyring_address_by_user_coded_name(&namespace, keyring_address(), "coded".to_string());
+        #[cfg(not(feature = "walletless"))]
+        let temp = accounts.check_keyring_address_by_user_address(&namespace, keyring_address(), user_address());
+        assert_eq!(temp, Err(KeyringError::ServicePaused));
+
+        accounts.unpause();
+        assert_eq!(accounts.pause_status, PauseStatus::default());
+
+        #[cfg(feature = "walletless")]
+        let temp = accounts.set_keyring_account_to_user_coded_name(&namespace, extra_address(), "extra".to_string(), keyring_data());
+        #[cfg(not(feature = "walletless"))]
+        let temp = accounts.set_keyring_account_to_user_address(&namespace, extra_address(), extra_address(), keyring_data());
+        assert!(temp.is_ok(), "Must be Ok");
+    }
+
+    #[test]
+    fn keyring_accounts_by_namespace() {
+        let mut accounts = KeyringAccounts::default();
+        let app_a = "app-a".to_string();
+        let app_b = "app-b".to_string();
+        let keyring_address = keyring_address();
+
+        // Must return an error (the namespace does not exists)
+        #[cfg(feature = "walletless")]
+        let temp = accounts.set_keyring_account_to_user_coded_name(&app_a, keyring_address, "coded".to_string(), keyring_data());
+        #[cfg(not(feature = "walletless"))]
+        let temp = accounts.set_keyring_account_to_user_address(&app_a, keyring_address, user_address(), keyring_data());
+        assert_eq!(temp, Err(KeyringError::NamespaceDoesNotExists));
+
+        assert!(accounts.add_namespace(app_a.clone()).is_ok(), "Must be Ok");
+        assert!(accounts.add_namespace(app_b.clone()).is_ok(), "Must be Ok");
+
+        // Must return an error (the namespace already exists)
+        assert_eq!(accounts.add_namespace(app_a.clone()), Err(KeyringError::NamespaceAlreadyExists));
+        assert_eq!(accounts.namespaces(), vec![app_a.clone(), app_b.clone()]);
+
+        // The same keyring address can be bound in each namespace
+        #[cfg(feature = "walletless")]
+        {
+            let temp = accounts.set_keyring_account_to_user_coded_name(&app_a, keyring_address, "coded".to_string(), keyring_data());
+            assert!(temp.is_ok(), "Must be Ok");
+
+            let temp = accounts.check_keyring_address_by_user_coded_name(&app_a, keyring_address, "coded".to_string());
+            assert!(temp.is_ok(), "Must be Ok");
+
+            // The session of the namespace A is not valid in the namespace B
+            let temp = accounts.check_keyring_address_by_user_coded_name(&app_b, keyring_address, "coded".to_string());
+            assert_eq!(temp, Err(KeyringError::UserDoesNotHasKeyringAccount));
+        }
+
+        #[cfg(feature = "signless")]
+        {
+            let temp = accounts.set_keyring_account_to_user_address(&app_b, keyring_address, user_address(), keyring_data());
+            assert!(temp.is_ok(), "Must be Ok");
+
+            let temp = accounts.check_keyring_address_by_user_address(&app_a, keyring_address, user_address());
+            assert_eq!(temp, Err(KeyringError::UserDoesNotHasKeyringAccount));
+        }
+
+        // Must return an error (the namespace is not empty)
+        assert_eq!(accounts.remove_namespace(&app_a), Err(KeyringError::NamespaceIsNotEmpty));
+
+        assert!(accounts.add_namespace("app-c".to_string()).is_ok(), "Must be Ok");
+        assert!(accounts.remove_namespace(&"app-c".to_string()).is_ok(), "Must be Ok");
+        assert_eq!(accounts.remove_namespace(&"app-c".to_string()), Err(KeyringError::NamespaceDoesNotExists));
+    }
+
+    #[cfg(all(feature = "signless", feature = "walletless"))]
+    mod invariants {
+        use proptest::prelude::*;
+
+        use super::super::*;
+        use super::keyring_data;
+
+        // Small pools of addresses and coded names, so the random sequences
+        // generate a lot of conflicts between the bindings
+        #[derive(Debug, Clone)]
+        enum Operation {
+            BindUserAddress {
+                keyring_address: u8,
+                user_address: u8
+            },
+            BindUserCodedName {
+                keyring_address: u8,
+                user_coded_name: u8
+            }
+        }
+
+        fn operation() -> impl Strategy<Value = Operation> {
+            prop_oneof![
+                (0u8..8, 0u8..8).prop_map(|(keyring_address, user_address)| {
+                    Operation::BindUserAddress { keyring_address, user_address }
+                }),
+                (0u8..8, 0u8..4).prop_map(|(keyring_address, user_coded_name)| {
+                    Operation::BindUserCodedName { keyring_address, user_coded_name }
+                })
+            ]
+        }
+
+        fn total_entries(accounts: &KeyringRegistry) -> (usize, usize, usize) {
+            (
+                accounts.keyring_accounts_address_by_user_address.len(),
+                accounts.keyring_accounts_address_by_user_coded_name.len(),
+                accounts.keyring_data_by_keyring_address.len()
+            )
+        }
+
+        proptest! {
+            #[test]
+            fn mutations_keep_state_consistent(operations in prop::collection::vec(operation(), 1..64)) {
+                let mut accounts = KeyringRegistry::default();
+
+                for operation in operations {
+                    let before = total_entries(&accounts);
+
+                    let result = match operation {
+                        Operation::BindUserAddress { keyring_address, user_address } => accounts
+                            .set_keyring_account_to_user_address(
+                                ActorId::from([keyring_address; 32]),
+                                ActorId::from([user_address; 32]),
+                                keyring_data()
+                            ),
+                        Operation::BindUserCodedName { keyring_address, user_coded_name } => accounts
+                            .set_keyring_account_to_user_coded_name(
+                                ActorId::from([keyring_address; 32]),
+                                format!("coded-name-{user_coded_name}"),
+                                keyring_data()
+                            )
+                    };
+
+                    let after = total_entries(&accounts);
+
+                    // A failed mutation must not change the state
+                    if result.is_err() {
+                        prop_assert_eq!(before, after);
+                    }
+
+                    let report = accounts.validate_invariants();
+                    prop_assert!(report.is_consistent(), "Inconsistent state: {:?}", report);
+                }
+            }
+        }
+    }
+
 
     fn user_address() -> ActorId {
         ActorId::from_str("0xce1e72b25e9bb6894faae535ee72f987168ed0b7af802a97ad5aeee300f85367")
@@ -0,0 +1,571 @@
+use sails_rs::prelude::*;
+
+use crate::service_enums::KeyringError;
+use super::{
+    KeyringData,
+    KeyringRegistry
+};
+
+// Blocks that a recovery request lives after its timelock (about one week with
+// blocks of 6 seconds), an expired request can be replaced by a new one
+pub const RECOVERY_REQUEST_EXPIRY_BLOCKS: u32 = 100_800;
+
+// # Recovery config of an user coded name (walletless)
+// Guardians that can rebind the coded name to a new keyring account if the
+// user loses the password of the current one
+#[derive(Encode, Decode, TypeInfo, Clone, Debug, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct RecoveryConfig {
+    // Accounts that can start and approve a recovery
+    pub guardians: Vec<ActorId>,
+    // Approvals needed to start the timelock (M of N guardians)
+    pub threshold: u32,
+    // Blocks to wait after the threshold is reached before the rebinding
+    pub delay_blocks: u32,
+}
+
+// # Recovery in progress of an user coded name
+#[derive(Encode, Decode, TypeInfo, Clone, Debug, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct RecoveryRequest {
+    // Keyring account that will be bound to the coded name
+    pub new_keyring_address: ActorId,
+    pub new_keyring_data: KeyringData,
+    // Guardians that approved the recovery
+    pub approvals: Vec<ActorId>,
+    // Block from which the recovery can be finalized (set when the threshold is reached)
+    pub executable_at: Option<u32>,
+    // Block from which the request is expired (it can not be approved or finalized),
+    // "RECOVERY_REQUEST_EXPIRY_BLOCKS" after the end of the timelock. Until the
+    // threshold is reached, it is computed as if the timelock started with the recovery
+    pub expires_at: u32,
+}
+
+impl RecoveryRequest {
+    pub fn is_expired(&self, current_block: u32) -> bool {
+        current_block >= self.expires_at
+    }
+}
+
+// ## Methods to manage the recovery of the user coded names
+impl KeyringRegistry {
+    // ### Set the recovery guardians of the user coded name
+    // Only the current keyring account of the coded name can set them
+    pub fn set_recovery_config(
+        &mut self,
+        keyring_address: ActorId,
+        user_coded_name: String,
+        config: RecoveryConfig,
+        current_block: u32
+    ) -> Result<(), KeyringError> {
+        self.check_keyring_address_by_user_coded_name(keyring_address, user_coded_name.clone())?;
+
+        let mut guardians = config.guardians.clone();
+        guardians.sort();
+        guardians.dedup();
+
+        // Guardians can not be repeated and the keyring account can not be a guardian
+        if guardians.is_empty()
+            || guardians.len() != config.guardians.len()
+            || guardians.contains(&keyring_address)
+        {
+            return Err(KeyringError::InvalidRecoveryGuardians);
+        }
+
+        if config.threshold == 0 || config.threshold as usize > guardians.len() {
+            return Err(KeyringError::InvalidRecoveryThreshold);
+        }
+
+        // The guardians can not be changed in the middle of a recovery
+        if self.recovery_request(&user_coded_name, current_block).is_some() {
+            return Err(KeyringError::RecoveryAlreadyInProgress);
+        }
+
+        self.recovery_request_by_user_coded_name
+            .remove(&user_coded_name);
+        self.recovery_config_by_user_coded_name
+            .insert(user_coded_name, config);
+
+        Ok(())
+    }
+
+    // ### Remove the recovery guardians of the user coded name
+    // Only the current keyring account of the coded name can remove them,
+    // the recovery in progress (if any) is cancelled
+    pub fn remove_recovery_config(
+        &mut self,
+        keyring_address: ActorId,
+        user_coded_name: String
+    ) -> Result<(), KeyringError> {
+        self.check_keyring_address_by_user_coded_name(keyring_address, user_coded_name.clone())?;
+
+        self.recovery_config_by_user_coded_name
+            .remove(&user_coded_name)
+            .ok_or(KeyringError::RecoveryIsNotConfigured)?;
+
+        self.recovery_request_by_user_coded_name
+            .remove(&user_coded_name);
+
+        Ok(())
+    }
+
+    // ### Start the recovery of the user coded name
+    // A guardian proposes the new keyring account, its call counts as an approval
+    // An expired request of the coded name is replaced by the new one
+    // Returns the block from which the recovery can be finalized (if any)
+    pub fn initiate_recovery(
+        &mut self,
+        guardian: ActorId,
+        user_coded_name: String,
+        new_keyring_address: ActorId,
+        new_keyring_data: KeyringData,
+        current_block: u32
+    ) -> Result<Option<u32>, KeyringError> {
+        let config = self.recovery_config(&user_coded_name)?;
+
+        if !config.guardians.contains(&guardian) {
+            return Err(KeyringError::ActionOnlyForGuardians);
+        }
+
+        if self.recovery_request(&user_coded_name, current_block).is_some() {
+            return Err(KeyringError::RecoveryAlreadyInProgress);
+        }
+
+        // The new keyring account can not be bound to another user
+        if self.keyring_data_by_keyring_address.contains_key(&new_keyring_address) {
+            return Err(KeyringError::KeyringAddressAlreadyEsists);
+        }
+
+        let executable_at = if config.threshold == 1 {
+            Some(current_block.saturating_add(config.delay_blocks))
+        } else {
+            None
+        };
+        let expires_at = current_block
+            .saturating_add(config.delay_blocks)
+            .saturating_add(RECOVERY_REQUEST_EXPIRY_BLOCKS);
+
+        self.recovery_request_by_user_coded_name.insert(
+            user_coded_name,
+            RecoveryRequest {
+                new_keyring_address,
+                new_keyring_data,
+                approvals: vec![guardian],
+                executable_at,
+                expires_at
+            }
+        );
+
+        Ok(executable_at)
+    }
+
+    // ### Approve the recovery in progress of the user coded name
+    // When the threshold is reached, the timelock starts
+    // Returns the block from which the recovery can be finalized (if any)
+    pub fn approve_recovery(
+        &mut self,
+        guardian: ActorId,
+        user_coded_name: String,
+        current_block: u32
+    ) -> Result<Option<u32>, KeyringError> {
+        let config = self.recovery_config(&user_coded_name)?.clone();
+
+        if !config.guardians.contains(&guardian) {
+            return Err(KeyringError::ActionOnlyForGuardians);
+        }
+
+        self.check_recovery_can_be_finalized(&user_coded_name, current_block)?;
+
+        let request = self.recovery_request_by_user_coded_name
+            .get_mut(&user_coded_name)
+            .ok_or(KeyringError::RecoveryIsNotInProgress)?;
+
+        if request.approvals.contains(&guardian) {
+            return Err(KeyringError::GuardianAlreadyApproved);
+        }
+
+        request.approvals.push(guardian);
+
+        // The window to finalize the recovery starts with the timelock, so late
+        // approvals can not leave an expiry before the end of the timelock
+        if request.executable_at.is_none() && request.approvals.len() >= config.threshold as usize {
+            let executable_at = current_block.saturating_add(config.delay_blocks);
+
+            request.executable_at = Some(executable_at);
+            request.expires_at = executable_at.saturating_add(RECOVERY_REQUEST_EXPIRY_BLOCKS);
+        }
+
+        Ok(request.executable_at)
+    }
+
+    // ### Cancel the recovery in progress of the user coded name
+    // Only the current keyring account of the coded name can cancel it
+    pub fn cancel_recovery(
+        &mut self,
+        keyring_address: ActorId,
+        user_coded_name: String
+    ) -> Result<(), KeyringError> {
+        self.check_keyring_address_by_user_coded_name(keyring_address, user_coded_name.clone())?;
+
+        self.recovery_request_by_user_coded_name
+            .remove(&user_coded_name)
+            .ok_or(KeyringError::RecoveryIsNotInProgress)?;
+
+        Ok(())
+    }
+
+    // ### Abort the recovery in progress of the user coded name
+    // Only guardians can abort it, and only if it can not be finalized anymore:
+    // the request expired or the new keyring account was bound after the
+    // recovery started. Then a new recovery can be initiated
+    pub fn abort_recovery(
+        &mut self,
+        guardian: ActorId,
+        user_coded_name: String,
+        current_block: u32
+    ) -> Result<(), KeyringError> {
+        if !self.recovery_config(&user_coded_name)?.guardians.contains(&guardian) {
+            return Err(KeyringError::ActionOnlyForGuardians);
+        }
+
+        if self.check_recovery_can_be_finalized(&user_coded_name, current_block).is_ok() {
+            return Err(KeyringError::RecoveryIsStillValid);
+        }
+
+        self.recovery_request_by_user_coded_name
+            .remove(&user_coded_name)
+            .ok_or(KeyringError::RecoveryIsNotInProgress)?;
+
+        Ok(())
+    }
+
+    // ### Finalize the recovery of the user coded name
+    // Only the new keyring account can finalize it, once the threshold was
+    // reached and the timelock expired. The coded name is rebound to the new
+    // keyring account and the data of the old one is removed
+    // Returns the old keyring address
+    pub fn finalize_recovery(
+        &mut self,
+        new_keyring_address: ActorId,
+        user_coded_name: String,
+        current_block: u32
+    ) -> Result<ActorId, KeyringError> {
+        let request = self.recovery_request_by_user_coded_name
+            .get(&user_coded_name)
+            .ok_or(KeyringError::RecoveryIsNotInProgress)?;
+
+        if request.new_keyring_address != new_keyring_address {
+            return Err(KeyringError::SessionHasInvalidCredentials);
+        }
+
+        self.check_recovery_can_be_finalized(&user_coded_name, current_block)?;
+
+        let executable_at = request.executable_at
+            .ok_or(KeyringError::RecoveryThresholdNotReached)?;
+
+        if current_block < executable_at {
+            return Err(KeyringError::RecoveryTimelockNotExpired {
+                executable_at,
+                current_block
+            });
+        }
+
+        let request = self.recovery_request_by_user_coded_name
+            .remove(&user_coded_name)
+            .ok_or(KeyringError::RecoveryIsNotInProgress)?;
+
+        let old_keyring_address = self.keyring_accounts_address_by_user_coded_name
+            .insert(user_coded_name, new_keyring_address)
+            .ok_or(KeyringError::UserDoesNotHasKeyringAccount)?;
+
+        self.keyring_data_by_keyring_address
+            .remove(&old_keyring_address);
+        self.keyring_data_by_keyring_address
+            .insert(new_keyring_address, request.new_keyring_data);
+
+        Ok(old_keyring_address)
+    }
+
+    // ### Check that the recovery in progress of the coded name can still be finalized
+    // It is not expired and its new keyring account was not bound after the
+    // recovery started (for example, by a batch binding)
+    fn check_recovery_can_be_finalized(&self, user_coded_name: &str, current_block: u32) -> Result<(), KeyringError> {
+        let request = self.recovery_request_by_user_coded_name
+            .get(user_coded_name)
+            .ok_or(KeyringError::RecoveryIsNotInProgress)?;
+
+        if request.is_expired(current_block) {
+            return Err(KeyringError::RecoveryRequestExpired);
+        }
+
+        if self.keyring_data_by_keyring_address.contains_key(&request.new_keyring_address) {
+            return Err(KeyringError::KeyringAddressAlreadyEsists);
+        }
+
+        Ok(())
+    }
+
+    // Recovery in progress of the coded name, None if there is not or if it expired
+    fn recovery_request(&self, user_coded_name: &str, current_block: u32) -> Option<&RecoveryRequest> {
+        self.recovery_request_by_user_coded_name
+            .get(user_coded_name)
+            .filter(|request| !request.is_expired(current_block))
+    }
+
+    fn recovery_config(&self, user_coded_name: &str) -> Result<&RecoveryConfig, KeyringError> {
+        self.recovery_config_by_user_coded_name
+            .get(user_coded_name)
+            .ok_or(KeyringError::RecoveryIsNotConfigured)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER_CODED_NAME: &str = "jsou3305bnsodheiJJCF9nc";
+
+    #[test]
+    fn recover_user_coded_name() {
+        let mut registry = registry_with_user_coded_name();
+        let new_keyring_address = address(10);
+
+        let temp = registry.set_recovery_config(keyring_address(), coded_name(), config(2, 10), 1);
+        assert!(temp.is_ok(), "Must be Ok");
+
+        let temp = registry.initiate_recovery(address(1), coded_name(), new_keyring_address, keyring_data(), 5);
+        assert!(temp.is_ok(), "Must be Ok");
+
+        // Must return an error (threshold not reached)
+        let temp = registry.finalize_recovery(new_keyring_address, coded_name(), 5);
+        assert_eq!(temp, Err(KeyringError::RecoveryThresholdNotReached));
+
+        // The threshold is reached, the timelock starts
+        let temp = registry.approve_recovery(address(2), coded_name(), 6);
+        assert_eq!(temp, Ok(Some(16)));
+
+        // Must return an error (timelock not expired)
+        let temp = registry.finalize_recovery(new_keyring_address, coded_name(), 15);
+        assert_eq!(temp, Err(KeyringError::RecoveryTimelockNotExpired { executable_at: 16, current_block: 15 }));
+
+        // Must return an error (only the new keyring account can finalize the recovery)
+        let temp = registry.finalize_recovery(address(2), coded_name(), 16);
+        assert_eq!(temp, Err(KeyringError::SessionHasInvalidCredentials));
+
+        let temp = registry.finalize_recovery(new_keyring_address, coded_name(), 16);
+        assert!(temp.is_ok(), "Must be Ok");
+
+        // The coded name is bound to the new keyring account
+        let temp = registry.check_keyring_address_by_user_coded_name(new_keyring_address, coded_name());
+        assert!(temp.is_ok(), "Must be Ok");
+
+        let temp = registry.check_keyring_address_by_user_coded_name(keyring_address(), coded_name());
+        assert_eq!(temp, Err(KeyringError::SessionHasInvalidCredentials));
+
+        assert!(!registry.keyring_data_by_keyring_address.contains_key(&keyring_address()), "Old keyring data must be removed");
+        assert!(registry.validate_invariants().is_consistent(), "Must be consistent");
+    }
+
+    #[test]
+    fn cancel_recovery_of_user_coded_name() {
+        let mut registry = registry_with_user_coded_name();
+        let new_keyring_address = address(10);
+
+        let temp = registry.set_recovery_config(keyring_address(), coded_name(), config(1, 10), 1);
+        assert!(temp.is_ok(), "Must be Ok");
+
+        // With a threshold of one, the timelock starts when the recovery is initiated
+        let temp = registry.initiate_recovery(address(1), coded_name(), new_keyring_address, keyring_data(), 5);
+        assert_eq!(temp, Ok(Some(15)));
+
+        // Must return an error (only the current keyring account can cancel it)
+        let temp = registry.cancel_recovery(address(1), coded_name());
+        assert_eq!(temp, Err(KeyringError::SessionHasInvalidCredentials));
+
+        let temp = registry.cancel_recovery(keyring_address(), coded_name());
+        assert!(temp.is_ok(), "Must be Ok");
+
+        // Must return an error (the recovery was cancelled)
+        let temp = registry.finalize_recovery(new_keyring_address, coded_name(), 20);
+        assert_eq!(temp, Err(KeyringError::RecoveryIsNotInProgress));
+
+        let temp = registry.check_keyring_address_by_user_coded_name(keyring_address(), coded_name());
+        assert!(temp.is_ok(), "Must be Ok");
+    }
+
+    #[test]
+    fn fail_recovery_of_user_coded_name() {
+        let mut registry = registry_with_user_coded_name();
+
+        // Must return an error (recovery is not configured)
+        let temp = registry.initiate_recovery(address(1), coded_name(), address(10), keyring_data(), 5);
+        assert_eq!(temp, Err(KeyringError::RecoveryIsNotConfigured));
+
+        // Must return an error (only the keyring account can set the guardians)
+        let temp = registry.set_recovery_config(address(1), coded_name(), config(2, 10), 1);
+        assert_eq!(temp, Err(KeyringError::SessionHasInvalidCredentials));
+
+        // Must return an error (invalid thresholds)
+        let temp = registry.set_recovery_config(keyring_address(), coded_name(), config(0, 10), 1);
+        assert_eq!(temp, Err(KeyringError::InvalidRecoveryThreshold));
+
+        let temp = registry.set_recovery_config(keyring_address(), coded_name(), config(4, 10), 1);
+        assert_eq!(temp, Err(KeyringError::InvalidRecoveryThreshold));
+
+        // Must return an error (repeated guardians)
+        let mut repeated = config(2, 10);
+        repeated.guardians.push(address(1));
+        let temp = registry.set_recovery_config(keyring_address(), coded_name(), repeated, 1);
+        assert_eq!(temp, Err(KeyringError::InvalidRecoveryGuardians));
+
+        let temp = registry.set_recovery_config(keyring_address(), coded_name(), config(2, 10), 1);
+        assert!(temp.is_ok(), "Must be Ok");
+
+        // Must return an error (only guardians can start the recovery)
+        let temp = registry.initiate_recovery(address(9), coded_name(), address(10), keyring_data(), 5);
+        assert_eq!(temp, Err(KeyringError::ActionOnlyForGuardians));
+
+        // Must return an error (the new keyring account is already bound)
+        let temp = registry.initiate_recovery(address(1), coded_name(), keyring_address(), keyring_data(), 5);
+        assert_eq!(temp, Err(KeyringError::KeyringAddressAlreadyEsists));
+
+        let temp = registry.initiate_recovery(address(1), coded_name(), address(10), keyring_data(), 5);
+        assert!(temp.is_ok(), "Must be Ok");
+
+        // Must return an error (guardian already approved)
+        let temp = registry.approve_recovery(address(1), coded_name(), 6);
+        assert_eq!(temp, Err(KeyringError::GuardianAlreadyApproved));
+
+        // Must return an error (recovery already in progress)
+        let temp = registry.initiate_recovery(address(2), coded_name(), address(11), keyring_data(), 6);
+        assert_eq!(temp, Err(KeyringError::RecoveryAlreadyInProgress));
+
+        let temp = registry.set_recovery_config(keyring_address(), coded_name(), config(1, 10), 1);
+        assert_eq!(temp, Err(KeyringError::RecoveryAlreadyInProgress));
+
+        // Removing the guardians cancels the recovery
+        let temp = registry.remove_recovery_config(keyring_address(), coded_name());
+        assert!(temp.is_ok(), "Must be Ok");
+
+        let temp = registry.approve_recovery(address(2), coded_name(), 6);
+        assert_eq!(temp, Err(KeyringError::RecoveryIsNotConfigured));
+    }
+
+    #[test]
+    fn expired_and_blocked_recoveries() {
+        let mut registry = registry_with_user_coded_name();
+        let new_keyring_address = address(10);
+
+        let temp = registry.set_recovery_config(keyring_address(), coded_name(), config(2, 10), 1);
+        assert!(temp.is_ok(), "Must be Ok");
+
+        let temp = registry.initiate_recovery(address(1), coded_name(), new_keyring_address, keyring_data(), 5);
+        assert!(temp.is_ok(), "Must be Ok");
+
+        // Must return an error (the recovery can still be finalized)
+        let temp = registry.abort_recovery(address(2), coded_name(), 6);
+        assert_eq!(temp, Err(KeyringError::RecoveryIsStillValid));
+
+        // The new keyring account is bound after the recovery started
+        registry
+            .set_keyring_account_to_user_coded_name(new_keyring_address, "other".to_string(), keyring_data())
+            .expect("Error while binding the keyring account");
+
+        // Must return an error (the approval does not count)
+        let temp = registry.approve_recovery(address(2), coded_name(), 6);
+        assert_eq!(temp, Err(KeyringError::KeyringAddressAlreadyEsists));
+
+        // Must return an error (only guardians can abort the recovery)
+        let temp = registry.abort_recovery(keyring_address(), coded_name(), 6);
+        assert_eq!(temp, Err(KeyringError::ActionOnlyForGuardians));
+
+        let temp = registry.abort_recovery(address(2), coded_name(), 6);
+        assert!(temp.is_ok(), "Must be Ok");
+
+        // The request expires after the timelock and the expiry blocks
+        let temp = registry.initiate_recovery(address(1), coded_name(), address(11), keyring_data(), 10);
+        assert!(temp.is_ok(), "Must be Ok");
+
+        let expires_at = 10 + 10 + RECOVERY_REQUEST_EXPIRY_BLOCKS;
+
+        let temp = registry.approve_recovery(address(2), coded_name(), expires_at);
+        assert_eq!(temp, Err(KeyringError::RecoveryRequestExpired));
+
+        let temp = registry.finalize_recovery(address(11), coded_name(), expires_at);
+        assert_eq!(temp, Err(KeyringError::RecoveryRequestExpired));
+
+        // An expired request is replaced by a new one
+        let temp = registry.initiate_recovery(address(2), coded_name(), address(12), keyring_data(), expires_at);
+        assert!(temp.is_ok(), "Must be Ok");
+
+        let request = registry.recovery_request_by_user_coded_name
+            .get(&coded_name())
+            .expect("Recovery must be in progress");
+        assert_eq!(request.new_keyring_address, address(12));
+    }
+
+    #[test]
+    fn late_threshold_moves_the_expiry_after_the_timelock() {
+        let mut registry = registry_with_user_coded_name();
+        let new_keyring_address = address(10);
+
+        let temp = registry.set_recovery_config(keyring_address(), coded_name(), config(2, 10), 1);
+        assert!(temp.is_ok(), "Must be Ok");
+
+        let temp = registry.initiate_recovery(address(1), coded_name(), new_keyring_address, keyring_data(), 5);
+        assert!(temp.is_ok(), "Must be Ok");
+
+        // The threshold is reached just before the first expiry, the timelock
+        // ends after it
+        let approved_at = 5 + 10 + RECOVERY_REQUEST_EXPIRY_BLOCKS - 1;
+        let executable_at = approved_at + 10;
+
+        let temp = registry.approve_recovery(address(2), coded_name(), approved_at);
+        assert_eq!(temp, Ok(Some(executable_at)));
+
+        let request = registry.recovery_request_by_user_coded_name
+            .get(&coded_name())
+            .expect("Recovery must be in progress");
+        assert_eq!(request.expires_at, executable_at + RECOVERY_REQUEST_EXPIRY_BLOCKS);
+
+        let temp = registry.finalize_recovery(new_keyring_address, coded_name(), executable_at);
+        assert!(temp.is_ok(), "Must be Ok");
+    }
+
+    fn registry_with_user_coded_name() -> KeyringRegistry {
+        let mut registry = KeyringRegistry::default();
+
+        registry
+            .set_keyring_account_to_user_coded_name(keyring_address(), coded_name(), keyring_data())
+            .expect("Error while binding the keyring account");
+
+        registry
+    }
+
+    // Guardians 1, 2 and 3
+    fn config(threshold: u32, delay_blocks: u32) -> RecoveryConfig {
+        RecoveryConfig {
+            guardians: vec![address(1), address(2), address(3)],
+            threshold,
+            delay_blocks
+        }
+    }
+
+    fn coded_name() -> String {
+        USER_CODED_NAME.to_string()
+    }
+
+    fn address(id: u64) -> ActorId {
+        ActorId::from(id)
+    }
+
+    fn keyring_address() -> ActorId {
+        address(100)
+    }
+
+    fn keyring_data() -> KeyringData {
+        KeyringData::new("KCIE83445HJSDS".to_string(), "fdnn3200jOIO92Noaa".to_string())
+    }
+}
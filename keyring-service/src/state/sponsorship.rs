@@ -0,0 +1,463 @@
+use sails_rs::{
+    prelude::*,
+    collections::HashMap
+};
+
+use crate::service_enums::KeyringError;
+use super::NamespaceId;
+
+// Max top-ups kept in the history of a budget, the oldest is dropped
+pub const MAX_TOP_UPS: usize = 20;
+
+// # Ledger of the sponsored gas budgets
+// Sponsors deposit value in the program and assign budgets to the keyring
+// accounts, that can request top-ups (value transfers) within their budget
+#[derive(Default, Clone)]
+pub struct SponsorLedger {
+    // Deposited value of each sponsor, not assigned to any budget
+    pub balances: HashMap<ActorId, u128>,
+    // Budget of each keyring account (by namespace)
+    pub budgets: HashMap<(NamespaceId, ActorId), SponsoredBudget>,
+}
+
+// # Budget assigned by a sponsor to a keyring account
+#[derive(Encode, Decode, TypeInfo, Clone, Debug, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct SponsoredBudget {
+    pub sponsor: ActorId,
+    // Value that can still be requested
+    pub remaining: u128,
+    // Max value that can be requested in each period
+    pub period_cap: u128,
+    // Blocks of each period
+    pub period_blocks: u32,
+    // Block where the current period started (with the first top-up
+    // after the end of the previous period)
+    pub period_start: u32,
+    // Value requested in the current period
+    pub period_spent: u128,
+    // Last top-ups of the keyring account (up to "MAX_TOP_UPS")
+    pub top_ups: Vec<TopUp>,
+}
+
+// # Top-up sent to a keyring account
+#[derive(Encode, Decode, TypeInfo, Clone, Copy, Debug, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct TopUp {
+    pub amount: u128,
+    pub block: u32,
+}
+
+impl SponsorLedger {
+    pub fn balance_of(&self, sponsor: ActorId) -> u128 {
+        self.balances
+            .get(&sponsor)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn budget(&self, namespace: &NamespaceId, keyring_address: ActorId) -> Option<&SponsoredBudget> {
+        self.budgets.get(&(namespace.clone(), keyring_address))
+    }
+
+    // ### Add the deposited value to the balance of the sponsor
+    // Returns the new balance of the sponsor
+    pub fn deposit(&mut self, sponsor: ActorId, amount: u128) -> Result<u128, KeyringError> {
+        if amount == 0 {
+            return Err(KeyringError::InvalidSponsorAmount);
+        }
+
+        let balance = self.balances
+            .entry(sponsor)
+            .or_default();
+
+        *balance = balance.saturating_add(amount);
+
+        Ok(*balance)
+    }
+
+    // ### Take value from the balance of the sponsor (not assigned to budgets)
+    // "transfer" sends the value, it is called once the withdrawal is checked and
+    // the balance only changes if it succeeds
+    // Returns the new balance of the sponsor
+    pub fn withdraw(
+        &mut self,
+        sponsor: ActorId,
+        amount: u128,
+        transfer: impl FnOnce() -> Result<(), KeyringError>
+    ) -> Result<u128, KeyringError> {
+        let balance = self.balance_of(sponsor);
+
+        if amount == 0 {
+            return Err(KeyringError::InvalidSponsorAmount);
+        }
+
+        if balance < amount {
+            return Err(KeyringError::NotEnoughSponsorBalance);
+        }
+
+        transfer()?;
+
+        self.set_balance(sponsor, balance - amount);
+
+        Ok(balance - amount)
+    }
+
+    // ### Assign a budget to a keyring account, from the balance of the sponsor
+    // If the sponsor already assigned a budget to the keyring account, the amount
+    // is added to it and the period limits are updated
+    // Returns the remaining value of the budget
+    pub fn assign_budget(
+        &mut self,
+        sponsor: ActorId,
+        namespace: &NamespaceId,
+        keyring_address: ActorId,
+        amount: u128,
+        period_cap: u128,
+        period_blocks: u32
+    ) -> Result<u128, KeyringError> {
+        let balance = self.balance_of(sponsor);
+
+        if amount == 0 || period_cap == 0 || period_blocks == 0 {
+            return Err(KeyringError::InvalidSponsorAmount);
+        }
+
+        if balance < amount {
+            return Err(KeyringError::NotEnoughSponsorBalance);
+        }
+
+        let budget = self.budgets
+            .entry((namespace.clone(), keyring_address))
+            .or_insert_with(|| SponsoredBudget {
+                sponsor,
+                remaining: 0,
+                period_cap,
+                period_blocks,
+                period_start: 0,
+                period_spent: 0,
+                top_ups: Vec::new()
+            });
+
+        // A keyring account only has one sponsor
+        if budget.sponsor != sponsor {
+            return Err(KeyringError::BudgetOfOtherSponsor);
+        }
+
+        budget.remaining = budget.remaining.saturating_add(amount);
+        budget.period_cap = period_cap;
+        budget.period_blocks = period_blocks;
+
+        let remaining = budget.remaining;
+
+        self.set_balance(sponsor, balance - amount);
+
+        Ok(remaining)
+    }
+
+    // ### Revoke the budget of a keyring account
+    // The remaining value goes back to the balance of the sponsor, returns it
+    pub fn revoke_budget(
+        &mut self,
+        sponsor: ActorId,
+        namespace: &NamespaceId,
+        keyring_address: ActorId
+    ) -> Result<u128, KeyringError> {
+        let key = (namespace.clone(), keyring_address);
+
+        let budget = self.budgets
+            .get(&key)
+            .ok_or(KeyringError::BudgetDoesNotExists)?;
+
+        if budget.sponsor != sponsor {
+            return Err(KeyringError::BudgetOfOtherSponsor);
+        }
+
+        let remaining = budget.remaining;
+
+        self.budgets.remove(&key);
+        self.set_balance(sponsor, self.balance_of(sponsor).saturating_add(remaining));
+
+        Ok(remaining)
+    }
+
+    // ### Take a top-up from the budget of the keyring account
+    // The amount can not exceed the remaining budget nor the cap of the
+    // current period (a new period starts after "period_blocks")
+    // "transfer" sends the value, it is called once the top-up is checked and
+    // the budget only changes if it succeeds
+    // Returns the remaining value of the budget
+    pub fn top_up(
+        &mut self,
+        namespace: &NamespaceId,
+        keyring_address: ActorId,
+        amount: u128,
+        current_block: u32,
+        transfer: impl FnOnce() -> Result<(), KeyringError>
+    ) -> Result<u128, KeyringError> {
+        let budget = self.budgets
+            .get_mut(&(namespace.clone(), keyring_address))
+            .ok_or(KeyringError::BudgetDoesNotExists)?;
+
+        if amount == 0 {
+            return Err(KeyringError::InvalidSponsorAmount);
+        }
+
+        let new_period = budget.period_spent == 0
+            || current_block >= budget.period_start.saturating_add(budget.period_blocks);
+        let period_spent = if new_period { 0 } else { budget.period_spent };
+
+        if amount > budget.remaining {
+            return Err(KeyringError::NotEnoughBudget);
+        }
+
+        if period_spent.saturating_add(amount) > budget.period_cap {
+            return Err(KeyringError::TopUpExceedsPeriodCap);
+        }
+
+        transfer()?;
+
+        if new_period {
+            budget.period_start = current_block;
+        }
+
+        budget.remaining -= amount;
+        budget.period_spent = period_spent + amount;
+
+        if budget.top_ups.len() >= MAX_TOP_UPS {
+            budget.top_ups.remove(0);
+        }
+
+        budget.top_ups.push(TopUp { amount, block: current_block });
+
+        Ok(budget.remaining)
+    }
+
+    // ### Move the budget of a keyring account to a new keyring address
+    // Used when a coded name is recovered with a new keyring account. If the new
+    // address had a budget (no longer usable), its value goes back to its sponsor
+    // Returns the moved budget (if any)
+    pub fn move_budget(
+        &mut self,
+        namespace: &NamespaceId,
+        old_keyring_address: ActorId,
+        new_keyring_address: ActorId
+    ) -> Option<&SponsoredBudget> {
+        let key = (namespace.clone(), new_keyring_address);
+
+        if let Some(stale) = self.budgets.remove(&key) {
+            self.refund(&stale);
+        }
+
+        let budget = self.budgets.remove(&(namespace.clone(), old_keyring_address))?;
+
+        self.budgets.insert(key.clone(), budget);
+        self.budgets.get(&key)
+    }
+
+    // ### Remove all the budgets of a namespace
+    // The remaining value of each budget goes back to the balance of its sponsor
+    // Returns the keyring addresses and the removed budgets
+    pub fn clear_namespace(&mut self, namespace: &NamespaceId) -> Vec<(ActorId, SponsoredBudget)> {
+        let keys: Vec<(NamespaceId, ActorId)> = self.budgets
+            .keys()
+            .filter(|(budget_namespace, _)| budget_namespace == namespace)
+            .cloned()
+            .collect();
+
+        let mut removed = Vec::with_capacity(keys.len());
+
+        for key in keys {
+            if let Some(budget) = self.budgets.remove(&key) {
+                self.refund(&budget);
+                removed.push((key.1, budget));
+            }
+        }
+
+        removed
+    }
+
+    fn refund(&mut self, budget: &SponsoredBudget) {
+        let balance = self.balance_of(budget.sponsor).saturating_add(budget.remaining);
+
+        self.set_balance(budget.sponsor, balance);
+    }
+
+    fn set_balance(&mut self, sponsor: ActorId, balance: u128) {
+        if balance == 0 {
+            self.balances.remove(&sponsor);
+        } else {
+            self.balances.insert(sponsor, balance);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_up_within_budget_and_period_cap() {
+        let mut ledger = SponsorLedger::default();
+        let namespace = namespace();
+
+        assert_eq!(ledger.deposit(sponsor(), 1_000), Ok(1_000));
+
+        // Must return an error (not enough balance)
+        let temp = ledger.assign_budget(sponsor(), &namespace, keyring_address(), 2_000, 100, 10);
+        assert_eq!(temp, Err(KeyringError::NotEnoughSponsorBalance));
+
+        let temp = ledger.assign_budget(sponsor(), &namespace, keyring_address(), 250, 100, 10);
+        assert_eq!(temp, Ok(250));
+        assert_eq!(ledger.balance_of(sponsor()), 750);
+
+        // The first period starts with the first top-up
+        assert_eq!(ledger.top_up(&namespace, keyring_address(), 60, 5, sent), Ok(190));
+
+        // Must return an error (period cap exceeded)
+        let temp = ledger.top_up(&namespace, keyring_address(), 60, 14, sent);
+        assert_eq!(temp, Err(KeyringError::TopUpExceedsPeriodCap));
+
+        // A new period starts
+        assert_eq!(ledger.top_up(&namespace, keyring_address(), 100, 15, sent), Ok(90));
+        assert_eq!(ledger.top_up(&namespace, keyring_address(), 90, 25, sent), Ok(0));
+
+        // Must return an error (budget exhausted)
+        let temp = ledger.top_up(&namespace, keyring_address(), 1, 35, sent);
+        assert_eq!(temp, Err(KeyringError::NotEnoughBudget));
+
+        let budget = ledger.budget(&namespace, keyring_address()).expect("Budget must exist");
+        assert_eq!(budget.top_ups, vec![
+            TopUp { amount: 60, block: 5 },
+            TopUp { amount: 100, block: 15 },
+            TopUp { amount: 90, block: 25 }
+        ]);
+    }
+
+    #[test]
+    fn manage_budgets() {
+        let mut ledger = SponsorLedger::default();
+        let namespace = namespace();
+        let other_sponsor = ActorId::from(2);
+
+        assert!(ledger.deposit(sponsor(), 1_000).is_ok(), "Must be Ok");
+        assert!(ledger.deposit(other_sponsor, 1_000).is_ok(), "Must be Ok");
+
+        assert_eq!(ledger.assign_budget(sponsor(), &namespace, keyring_address(), 300, 100, 10), Ok(300));
+        assert_eq!(ledger.assign_budget(sponsor(), &namespace, keyring_address(), 200, 100, 10), Ok(500));
+
+        // Must return an error (the keyring account has another sponsor)
+        let temp = ledger.assign_budget(other_sponsor, &namespace, keyring_address(), 200, 100, 10);
+        assert_eq!(temp, Err(KeyringError::BudgetOfOtherSponsor));
+
+        let temp = ledger.revoke_budget(other_sponsor, &namespace, keyring_address());
+        assert_eq!(temp, Err(KeyringError::BudgetOfOtherSponsor));
+
+        assert!(ledger.top_up(&namespace, keyring_address(), 100, 1, sent).is_ok(), "Must be Ok");
+
+        // The remaining budget goes back to the sponsor
+        assert_eq!(ledger.revoke_budget(sponsor(), &namespace, keyring_address()), Ok(400));
+        assert_eq!(ledger.balance_of(sponsor()), 900);
+
+        let temp = ledger.top_up(&namespace, keyring_address(), 1, 2, sent);
+        assert_eq!(temp, Err(KeyringError::BudgetDoesNotExists));
+
+        assert_eq!(ledger.withdraw(sponsor(), 900, sent), Ok(0));
+
+        // Must return an error (not enough balance)
+        assert_eq!(ledger.withdraw(sponsor(), 1, sent), Err(KeyringError::NotEnoughSponsorBalance));
+    }
+
+    #[test]
+    fn failed_transfers_do_not_change_the_ledger() {
+        let mut ledger = SponsorLedger::default();
+        let namespace = namespace();
+
+        assert!(ledger.deposit(sponsor(), 1_000).is_ok(), "Must be Ok");
+        assert!(ledger.assign_budget(sponsor(), &namespace, keyring_address(), 500, 100, 10).is_ok(), "Must be Ok");
+        assert!(ledger.top_up(&namespace, keyring_address(), 50, 1, sent).is_ok(), "Must be Ok");
+
+        let ledger_before = ledger.clone();
+
+        // Must return an error (the value could not be sent)
+        let temp = ledger.withdraw(sponsor(), 100, not_sent);
+        assert_eq!(temp, Err(KeyringError::ValueTransferFailed));
+
+        let temp = ledger.top_up(&namespace, keyring_address(), 10, 20, not_sent);
+        assert_eq!(temp, Err(KeyringError::ValueTransferFailed));
+
+        assert_eq!(ledger.balances, ledger_before.balances);
+        assert_eq!(ledger.budgets, ledger_before.budgets);
+    }
+
+    #[test]
+    fn top_ups_history_is_capped() {
+        let mut ledger = SponsorLedger::default();
+        let namespace = namespace();
+        let top_ups = MAX_TOP_UPS as u32 + 5;
+
+        assert!(ledger.deposit(sponsor(), 1_000).is_ok(), "Must be Ok");
+        assert!(ledger.assign_budget(sponsor(), &namespace, keyring_address(), 1_000, 1, 1).is_ok(), "Must be Ok");
+
+        for block in 0..top_ups {
+            assert!(ledger.top_up(&namespace, keyring_address(), 1, block, sent).is_ok(), "Must be Ok");
+        }
+
+        // Only the last top-ups are kept
+        let budget = ledger.budget(&namespace, keyring_address()).expect("Budget must exist");
+        assert_eq!(budget.top_ups.len(), MAX_TOP_UPS);
+        assert_eq!(budget.top_ups[0], TopUp { amount: 1, block: 5 });
+        assert_eq!(budget.remaining, 1_000 - top_ups as u128);
+    }
+
+    #[test]
+    fn move_and_clear_budgets() {
+        let mut ledger = SponsorLedger::default();
+        let namespace = namespace();
+        let other_namespace = "other-app".to_string();
+        let new_keyring_address = ActorId::from(101);
+
+        assert!(ledger.deposit(sponsor(), 1_000).is_ok(), "Must be Ok");
+        assert!(ledger.assign_budget(sponsor(), &namespace, keyring_address(), 300, 100, 10).is_ok(), "Must be Ok");
+        assert!(ledger.assign_budget(sponsor(), &namespace, new_keyring_address, 200, 100, 10).is_ok(), "Must be Ok");
+        assert!(ledger.assign_budget(sponsor(), &other_namespace, keyring_address(), 100, 100, 10).is_ok(), "Must be Ok");
+
+        // The stale budget of the new address goes back to the sponsor
+        let moved = ledger.move_budget(&namespace, keyring_address(), new_keyring_address).cloned();
+        assert_eq!(moved.map(|budget| budget.remaining), Some(300));
+        assert_eq!(ledger.balance_of(sponsor()), 600);
+        assert_eq!(ledger.budget(&namespace, keyring_address()), None);
+
+        // Nothing to move
+        assert_eq!(ledger.move_budget(&namespace, keyring_address(), ActorId::from(102)), None);
+
+        let removed = ledger.clear_namespace(&namespace);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].0, new_keyring_address);
+        assert_eq!(ledger.balance_of(sponsor()), 900);
+
+        // Budgets of other namespaces are kept
+        assert!(ledger.budget(&other_namespace, keyring_address()).is_some(), "Must be Some");
+    }
+
+    fn sent() -> Result<(), KeyringError> {
+        Ok(())
+    }
+
+    fn not_sent() -> Result<(), KeyringError> {
+        Err(KeyringError::ValueTransferFailed)
+    }
+
+    fn namespace() -> NamespaceId {
+        "app".to_string()
+    }
+
+    fn sponsor() -> ActorId {
+        ActorId::from(1)
+    }
+
+    fn keyring_address() -> ActorId {
+        ActorId::from(100)
+    }
+}
@@ -0,0 +1,702 @@
+#![cfg(all(feature = "signless", feature = "walletless"))]
+
+use gtest::{constants::{DEFAULT_USER_ALICE, UNITS}, Program, System};
+use keyring_service::{
+    service_enums::{KeyringError, KeyringEvent, KeyringQueryEvent},
+    state::{
+        migration::{BindingsBatch, ExportCursor, ImportProgress},
+        sponsorship::TopUp,
+        recovery::RecoveryConfig,
+        KeyringData,
+        KeyringRegistry,
+        PauseStatus
+    }
+};
+use proptest::prelude::*;
+use sails_rs::{prelude::*, ActorId};
+use std::cell::RefCell;
+
+const ADMIN: u64 = DEFAULT_USER_ALICE;
+const SERVICE: &str = "KeyringService";
+const NAMESPACE: &str = "app";
+
+// # Test environment
+// Program with the keyring service deployed by the ADMIN account,
+// with the NAMESPACE already added
+struct Env {
+    system: System,
+    // Payloads of the events emitted by the last call
+    events: RefCell<Vec<Vec<u8>>>
+}
+
+impl Env {
+    fn new() -> Self {
+        let env = Self { system: System::new(), events: RefCell::new(Vec::new()) };
+        env.deploy(100);
+
+        let result: KeyringEvent = env.call(ADMIN.into(), "AddNamespace", NAMESPACE);
+        assert_eq!(result, KeyringEvent::NamespaceAdded(NAMESPACE.to_string()));
+
+        env
+    }
+
+    fn program(&self, program_id: u64) -> Program<'_> {
+        self.system
+            .get_program(program_id)
+            .expect("Program is not deployed")
+    }
+
+    // ## Deploys another program with the keyring service (by the ADMIN account)
+    fn deploy(&self, program_id: u64) {
+        let program = Program::from_binary_with_id(&self.system, program_id, keyring_service_wasm::WASM_BINARY);
+        program.send_bytes(ADMIN, "New".encode());
+        self.system.run_next_block();
+    }
+
+    // ## Gives balance to an account to send messages
+    fn fund(&self, address: ActorId) {
+        if self.system.balance_of(address) == 0 {
+            self.system.mint_to(address, 1_000 * UNITS);
+        }
+    }
+
+    // ## Sends a sails message to the keyring service and decodes the reply
+    fn call<A: Encode, R: Decode>(&self, from: ActorId, method: &str, args: A) -> R {
+        self.call_program(100, from, method, args)
+    }
+
+    // ## Sends a sails message to the keyring service of the given program
+    fn call_program<A: Encode, R: Decode>(&self, program_id: u64, from: ActorId, method: &str, args: A) -> R {
+        self.call_program_with_value(program_id, from, method, args, 0)
+    }
+
+    // ## Sends a sails message with value to the keyring service of the given program
+    fn call_program_with_value<A: Encode, R: Decode>(
+        &self,
+        program_id: u64,
+        from: ActorId,
+        method: &str,
+        args: A,
+        value: u128
+    ) -> R {
+        self.fund(from);
+
+        let message_id = self.program(program_id)
+            .send_bytes_with_value(from, (SERVICE, method, args).encode(), value);
+        let result = self.system.run_next_block();
+
+        *self.events.borrow_mut() = result
+            .log()
+            .iter()
+            .filter(|log| log.destination() == ActorId::zero())
+            .map(|log| log.payload().to_vec())
+            .collect();
+
+        let reply = result
+            .log()
+            .iter()
+            .find(|log| log.reply_to() == Some(message_id))
+            .expect("The program did not reply");
+
+        let route = (SERVICE, method).encode();
+        let payload = reply.payload();
+
+        assert!(payload.starts_with(&route), "Reply without the service route");
+
+        R::decode(&mut &payload[route.len()..])
+            .expect("Error while decoding the reply")
+    }
+
+    fn bind_to_user_address(&self, keyring_address: ActorId, user_address: ActorId) -> KeyringEvent {
+        self.call(keyring_address, "BindKeyringDataToUserAddress", (NAMESPACE, user_address, keyring_data(keyring_address)))
+    }
+
+    fn bind_to_user_coded_name(&self, keyring_address: ActorId, user_coded_name: &str) -> KeyringEvent {
+        self.call(keyring_address, "BindKeyringDataToUserCodedName", (NAMESPACE, user_coded_name, keyring_data(keyring_address)))
+    }
+
+    // ## Events emitted by the last call, each one encoded with the route
+    // of the service and the name of the event, followed by its fields
+    fn last_events(&self) -> Vec<Vec<u8>> {
+        self.events.borrow().clone()
+    }
+
+    fn invariants(&self) -> KeyringQueryEvent {
+        self.call(ADMIN.into(), "KeyringStateInvariants", NAMESPACE)
+    }
+}
+
+fn keyring_data(keyring_address: ActorId) -> KeyringData {
+    KeyringData::new(
+        format!("{keyring_address:?}"),
+        String::from("fdnn3200jOIO92Noaa")
+    )
+}
+
+fn address(id: u64) -> ActorId {
+    ActorId::from(id)
+}
+
+#[test]
+fn bind_keyring_account_to_user_address() {
+    let env = Env::new();
+    let user_address = address(1);
+    let keyring_address = address(2);
+
+    let result = env.bind_to_user_address(keyring_address, user_address);
+    assert_eq!(result, KeyringEvent::KeyringAccountSet);
+
+    let result: KeyringQueryEvent = env.call(user_address, "KeyringAddressFromUserAddress", (NAMESPACE, user_address));
+    assert_eq!(result, KeyringQueryEvent::KeyringAccountAddress(Some(keyring_address)));
+
+    let result: KeyringQueryEvent = env.call(user_address, "KeyringAccountData", (NAMESPACE, keyring_address));
+    assert_eq!(result, KeyringQueryEvent::KeyringAccountData(Some(keyring_data(keyring_address))));
+
+    // User without keyring account
+    let result: KeyringQueryEvent = env.call(user_address, "KeyringAddressFromUserAddress", (NAMESPACE, address(3)));
+    assert_eq!(result, KeyringQueryEvent::KeyringAccountAddress(None));
+}
+
+#[test]
+fn bind_keyring_account_to_user_coded_name() {
+    let env = Env::new();
+    let user_coded_name = "jsou3305bnsodheiJJCF9nc";
+    let keyring_address = address(2);
+
+    let result: KeyringQueryEvent = env.call(keyring_address, "UserCodedNameIsTaken", (NAMESPACE, user_coded_name));
+    assert_eq!(result, KeyringQueryEvent::UserCodedNameIsTaken(false));
+
+    let result = env.bind_to_user_coded_name(keyring_address, user_coded_name);
+    assert_eq!(result, KeyringEvent::KeyringAccountSet);
+
+    let result: KeyringQueryEvent = env.call(keyring_address, "KeyringAddressFromUserCodedName", (NAMESPACE, user_coded_name));
+    assert_eq!(result, KeyringQueryEvent::KeyringAccountAddress(Some(keyring_address)));
+
+    let result: KeyringQueryEvent = env.call(keyring_address, "UserCodedNameIsTaken", (NAMESPACE, user_coded_name));
+    assert_eq!(result, KeyringQueryEvent::UserCodedNameIsTaken(true));
+}
+
+#[test]
+fn fail_bind_keyring_accounts() {
+    let env = Env::new();
+    let user_address = address(1);
+    let keyring_address = address(2);
+    let extra_address = address(3);
+
+    // Must return an error (user and keyring address are the same)
+    let result = env.bind_to_user_address(user_address, user_address);
+    assert_eq!(result, KeyringEvent::Error(KeyringError::UserAndKeyringAddressAreTheSame));
+
+    let result = env.bind_to_user_address(keyring_address, user_address);
+    assert_eq!(result, KeyringEvent::KeyringAccountSet);
+
+    // Must return an error (user address already exists)
+    let result = env.bind_to_user_address(extra_address, user_address);
+    assert_eq!(result, KeyringEvent::Error(KeyringError::UserAddressAlreadyExists));
+
+    // Must return an error (keyring address already bound to a wallet)
+    let result = env.bind_to_user_coded_name(keyring_address, "coded-name");
+    assert_eq!(result, KeyringEvent::Error(KeyringError::KeyringAddressAlreadyEsists));
+
+    let result = env.bind_to_user_coded_name(extra_address, "coded-name");
+    assert_eq!(result, KeyringEvent::KeyringAccountSet);
+
+    // Must return an error (coded name already exists)
+    let result = env.bind_to_user_coded_name(address(4), "coded-name");
+    assert_eq!(result, KeyringEvent::Error(KeyringError::UserCodedNameAlreadyExists));
+
+    // Must return an error (keyring address already bound to a coded name)
+    let result = env.bind_to_user_address(extra_address, address(5));
+    assert_eq!(result, KeyringEvent::Error(KeyringError::KeyringAddressAlreadyEsists));
+
+    assert_eq!(env.invariants(), KeyringQueryEvent::InvariantsReport(Default::default()));
+}
+
+#[test]
+fn admin_queries_and_commands() {
+    let env = Env::new();
+    let stranger = address(1);
+
+    // Must return an error (only admins can get the report)
+    let result: KeyringQueryEvent = env.call(stranger, "KeyringStateInvariants", NAMESPACE);
+    assert_eq!(result, KeyringQueryEvent::Error(KeyringError::ActionOnlyForAdmins));
+
+    // Must return an error (only admins can add admins)
+    let result: KeyringEvent = env.call(stranger, "AddAdmin", stranger);
+    assert_eq!(result, KeyringEvent::Error(KeyringError::ActionOnlyForAdmins));
+
+    let result: KeyringEvent = env.call(ADMIN.into(), "AddAdmin", stranger);
+    assert_eq!(result, KeyringEvent::AdminAdded(stranger));
+
+    let result: KeyringQueryEvent = env.call(stranger, "Admins", ());
+    assert_eq!(result, KeyringQueryEvent::Admins(vec![ADMIN.into(), stranger]));
+}
+
+#[test]
+fn bind_keyring_accounts_in_batch() {
+    let env = Env::new();
+    let registrar = address(1);
+    let bindings = vec![
+        (String::from("coded-a"), address(10), keyring_data(address(10))),
+        (String::from("coded-a"), address(11), keyring_data(address(11))),
+        (String::from("coded-b"), address(12), keyring_data(address(12)))
+    ];
+
+    // Must return an error (only admins and registrars can bind in batches)
+    let result: KeyringEvent = env.call(registrar, "BindKeyringDataToUserCodedNames", (NAMESPACE, bindings.clone()));
+    assert_eq!(result, KeyringEvent::Error(KeyringError::ActionOnlyForAdminsOrRegistrars));
+
+    let result: KeyringEvent = env.call(ADMIN.into(), "AddRegistrar", registrar);
+    assert_eq!(result, KeyringEvent::RegistrarAdded(registrar));
+
+    let result: KeyringQueryEvent = env.call(registrar, "Registrars", ());
+    assert_eq!(result, KeyringQueryEvent::Registrars(vec![registrar]));
+
+    // The repeated coded name fails without aborting the batch
+    let result: KeyringEvent = env.call(registrar, "BindKeyringDataToUserCodedNames", (NAMESPACE, bindings));
+    assert_eq!(result, KeyringEvent::KeyringAccountsSet(vec![
+        Ok(()),
+        Err(KeyringError::UserCodedNameAlreadyExists),
+        Ok(())
+    ]));
+
+    let result: KeyringQueryEvent = env.call(registrar, "KeyringAddressFromUserCodedName", (NAMESPACE, "coded-b"));
+    assert_eq!(result, KeyringQueryEvent::KeyringAccountAddress(Some(address(12))));
+
+    assert_eq!(env.invariants(), KeyringQueryEvent::InvariantsReport(Default::default()));
+}
+
+#[test]
+fn top_up_keyring_account_from_sponsored_budget() {
+    let env = Env::new();
+    let sponsor = address(1);
+    let user_address = address(2);
+    let keyring_address = address(3);
+
+    // Must return an error (the keyring account is not bound)
+    let result: KeyringEvent = env.call(sponsor, "AssignBudget", (NAMESPACE, keyring_address, 10 * UNITS, 5 * UNITS, 100u32));
+    assert_eq!(result, KeyringEvent::Error(KeyringError::KeyringAccountDoesNotExists));
+
+    assert_eq!(env.bind_to_user_address(keyring_address, user_address), KeyringEvent::KeyringAccountSet);
+
+    let result: KeyringEvent = env.call_program_with_value(100, sponsor, "DepositSponsorship", (), 20 * UNITS);
+    assert_eq!(result, KeyringEvent::SponsorDeposited { sponsor, amount: 20 * UNITS, balance: 20 * UNITS });
+
+    let result: KeyringEvent = env.call(sponsor, "AssignBudget", (NAMESPACE, keyring_address, 10 * UNITS, 5 * UNITS, 100u32));
+    assert_eq!(result, KeyringEvent::BudgetAssigned { sponsor, keyring_address, remaining: 10 * UNITS });
+
+    let result: KeyringQueryEvent = env.call(sponsor, "SponsorBalance", sponsor);
+    assert_eq!(result, KeyringQueryEvent::SponsorBalance(10 * UNITS));
+
+    let keyring_balance = env.system.balance_of(keyring_address);
+
+    let result: KeyringEvent = env.call(keyring_address, "RequestTopUp", (NAMESPACE, 4 * UNITS));
+    assert_eq!(result, KeyringEvent::ToppedUp { keyring_address, amount: 4 * UNITS, remaining: 6 * UNITS });
+    assert_eq!(env.last_events(), vec![(SERVICE, "ToppedUp", NAMESPACE, keyring_address, 4 * UNITS, 6 * UNITS).encode()]);
+
+    // Must return an error (period cap exceeded)
+    let result: KeyringEvent = env.call(keyring_address, "RequestTopUp", (NAMESPACE, 2 * UNITS));
+    assert_eq!(result, KeyringEvent::Error(KeyringError::TopUpExceedsPeriodCap));
+    assert!(env.last_events().is_empty(), "Failed top-ups must not emit events");
+
+    // The value is sent to the keyring address (minus the gas of its messages)
+    assert!(env.system.balance_of(keyring_address) > keyring_balance, "Keyring account must be topped up");
+
+    let result: KeyringQueryEvent = env.call(sponsor, "SponsoredBudget", (NAMESPACE, keyring_address));
+    let KeyringQueryEvent::SponsoredBudget(Some(budget)) = result else {
+        core::panic!("Budget must exist: {result:?}");
+    };
+    assert_eq!(budget.remaining, 6 * UNITS);
+    assert_eq!(budget.top_ups.len(), 1);
+    assert!(matches!(budget.top_ups[0], TopUp { amount, .. } if amount == 4 * UNITS));
+
+    let result: KeyringEvent = env.call(sponsor, "RevokeBudget", (NAMESPACE, keyring_address));
+    assert_eq!(result, KeyringEvent::BudgetRevoked { sponsor, keyring_address, returned: 6 * UNITS });
+
+    let result: KeyringEvent = env.call(sponsor, "WithdrawSponsorship", 16 * UNITS);
+    assert_eq!(result, KeyringEvent::SponsorWithdrawn { sponsor, amount: 16 * UNITS, balance: 0 });
+    assert_eq!(env.last_events(), vec![(SERVICE, "SponsorWithdrawn", sponsor, 16 * UNITS, 0u128).encode()]);
+}
+
+#[test]
+fn pause_service() {
+    let env = Env::new();
+    let user_address = address(1);
+    let keyring_address = address(2);
+
+    // Must return an error (only admins can pause the service)
+    let result: KeyringEvent = env.call(user_address, "Pause", false);
+    assert_eq!(result, KeyringEvent::Error(KeyringError::ActionOnlyForAdmins));
+
+    let status = PauseStatus { paused: true, checks_paused: false };
+    let result: KeyringEvent = env.call(ADMIN.into(), "Pause", false);
+    assert_eq!(result, KeyringEvent::Paused(status));
+
+    let result: KeyringQueryEvent = env.call(user_address, "PauseStatus", ());
+    assert_eq!(result, KeyringQueryEvent::PauseStatus(status));
+
+    // Must return an error (the service is paused)
+    let result = env.bind_to_user_address(keyring_address, user_address);
+    assert_eq!(result, KeyringEvent::Error(KeyringError::ServicePaused));
+
+    let result = env.bind_to_user_coded_name(keyring_address, "coded-name");
+    assert_eq!(result, KeyringEvent::Error(KeyringError::ServicePaused));
+
+    // Queries still work
+    let result: KeyringQueryEvent = env.call(user_address, "KeyringAddressFromUserAddress", (NAMESPACE, user_address));
+    assert_eq!(result, KeyringQueryEvent::KeyringAccountAddress(None));
+
+    let result: KeyringEvent = env.call(ADMIN.into(), "Unpause", ());
+    assert_eq!(result, KeyringEvent::Unpaused);
+
+    let result = env.bind_to_user_address(keyring_address, user_address);
+    assert_eq!(result, KeyringEvent::KeyringAccountSet);
+}
+
+#[test]
+fn migrate_bindings_to_new_program() {
+    let env = Env::new();
+    let new_program = 200;
+
+    for id in 1..=3u64 {
+        assert_eq!(env.bind_to_user_address(address(id + 10), address(id)), KeyringEvent::KeyringAccountSet);
+    }
+
+    assert_eq!(env.bind_to_user_coded_name(address(20), "coded-name"), KeyringEvent::KeyringAccountSet);
+
+    // Must return an error (only admins can export the bindings)
+    let result: KeyringQueryEvent = env.call(address(1), "ExportBindings", (NAMESPACE, ExportCursor::default(), 2u32));
+    assert_eq!(result, KeyringQueryEvent::Error(KeyringError::ActionOnlyForAdmins));
+
+    // Must return an error (the service must be paused during the export)
+    let result: KeyringQueryEvent = env.call(ADMIN.into(), "ExportBindings", (NAMESPACE, ExportCursor::default(), 2u32));
+    assert_eq!(result, KeyringQueryEvent::Error(KeyringError::ServiceIsNotPaused));
+
+    let result: KeyringEvent = env.call(ADMIN.into(), "Pause", false);
+    assert_eq!(result, KeyringEvent::Paused(PauseStatus { paused: true, checks_paused: false }));
+
+    env.deploy(new_program);
+    let result: KeyringEvent = env.call_program(new_program, ADMIN.into(), "AddNamespace", NAMESPACE);
+    assert_eq!(result, KeyringEvent::NamespaceAdded(NAMESPACE.to_string()));
+
+    let mut cursor = Some(ExportCursor::default());
+    let mut offset = 0u32;
+    let mut exported = ImportProgress::default();
+
+    while let Some(next) = cursor {
+        let result: KeyringQueryEvent = env.call(ADMIN.into(), "ExportBindings", (NAMESPACE, next, 2u32));
+        let KeyringQueryEvent::BindingsExported(batch) = result else {
+            core::panic!("Bindings must be exported: {result:?}");
+        };
+
+        assert_eq!(batch.total, 4);
+
+        offset += batch.records.len() as u32;
+        cursor = batch.next.clone();
+
+        // Must return an error (a changed batch)
+        let mut corrupted: BindingsBatch = batch.clone();
+        corrupted.count += 1;
+        let result: KeyringEvent = env.call_program(new_program, ADMIN.into(), "ImportBindings", (NAMESPACE, corrupted));
+        assert_eq!(result, KeyringEvent::Error(KeyringError::ImportIntegrityMismatch));
+
+        exported = ImportProgress { count: batch.count, running_hash: batch.running_hash, total: batch.total };
+
+        // Must return an error (the last batch is not imported)
+        if batch.next.is_none() {
+            let result: KeyringEvent = env.call_program(new_program, ADMIN.into(), "FinishSeeding", vec![(NAMESPACE, exported)]);
+            assert_eq!(result, KeyringEvent::Error(KeyringError::ImportIsIncomplete));
+        }
+
+        let result: KeyringEvent = env.call_program(new_program, ADMIN.into(), "ImportBindings", (NAMESPACE, batch));
+        assert_eq!(result, KeyringEvent::BindingsImported(exported));
+    }
+
+    assert_eq!(offset, 4);
+
+    // Must return an error (the imported namespace is not in the export)
+    let result: KeyringEvent = env.call_program(new_program, ADMIN.into(), "FinishSeeding", Vec::<(String, ImportProgress)>::new());
+    assert_eq!(result, KeyringEvent::Error(KeyringError::ImportIsIncomplete));
+
+    let result: KeyringEvent = env.call_program(new_program, ADMIN.into(), "FinishSeeding", vec![(NAMESPACE, exported)]);
+    assert_eq!(result, KeyringEvent::SeedingFinished);
+
+    // Must return an error (the seeding phase is finished)
+    let result: KeyringEvent = env.call_program(new_program, ADMIN.into(), "ImportBindings", (NAMESPACE, BindingsBatch::default()));
+    assert_eq!(result, KeyringEvent::Error(KeyringError::SeedingIsFinished));
+
+    // The users keep their sessions in the new program
+    let result: KeyringQueryEvent = env.call_program(new_program, address(2), "KeyringAddressFromUserAddress", (NAMESPACE, address(2)));
+    assert_eq!(result, KeyringQueryEvent::KeyringAccountAddress(Some(address(12))));
+
+    let result: KeyringQueryEvent = env.call_program(new_program, address(20), "KeyringAccountData", (NAMESPACE, address(20)));
+    assert_eq!(result, KeyringQueryEvent::KeyringAccountData(Some(keyring_data(address(20)))));
+}
+
+#[test]
+fn keyring_accounts_by_namespace() {
+    let env = Env::new();
+    let user_address = address(1);
+    let keyring_address = address(2);
+    let other_namespace = "other-app";
+
+    // Must return an error (only admins can add namespaces)
+    let result: KeyringEvent = env.call(user_address, "AddNamespace", other_namespace);
+    assert_eq!(result, KeyringEvent::Error(KeyringError::ActionOnlyForAdmins));
+
+    // Must return an error (the namespace does not exists)
+    let result: KeyringEvent = env.call(
+        keyring_address,
+        "BindKeyringDataToUserAddress",
+        (other_namespace, user_address, keyring_data(keyring_address))
+    );
+    assert_eq!(result, KeyringEvent::Error(KeyringError::NamespaceDoesNotExists));
+
+    let result: KeyringEvent = env.call(ADMIN.into(), "AddNamespace", other_namespace);
+    assert_eq!(result, KeyringEvent::NamespaceAdded(other_namespace.to_string()));
+
+    let result: KeyringQueryEvent = env.call(user_address, "Namespaces", ());
+    assert_eq!(result, KeyringQueryEvent::Namespaces(vec![NAMESPACE.to_string(), other_namespace.to_string()]));
+
+    let result = env.bind_to_user_address(keyring_address, user_address);
+    assert_eq!(result, KeyringEvent::KeyringAccountSet);
+
+    // The keyring account of the namespace is not bound in the other namespace
+    let result: KeyringQueryEvent = env.call(user_address, "KeyringAddressFromUserAddress", (other_namespace, user_address));
+    assert_eq!(result, KeyringQueryEvent::KeyringAccountAddress(None));
+
+    // Must return an error (the namespace is not empty)
+    let result: KeyringEvent = env.call(ADMIN.into(), "RemoveNamespace", NAMESPACE);
+    assert_eq!(result, KeyringEvent::Error(KeyringError::NamespaceIsNotEmpty));
+
+    let result: KeyringEvent = env.call(ADMIN.into(), "RemoveNamespace", other_namespace);
+    assert_eq!(result, KeyringEvent::NamespaceRemoved(other_namespace.to_string()));
+}
+
+#[test]
+fn recover_user_coded_name_with_guardians() {
+    let env = Env::new();
+    let user_coded_name = "jsou3305bnsodheiJJCF9nc";
+    let keyring_address = address(2);
+    let new_keyring_address = address(3);
+    let guardians = vec![address(10), address(11), address(12)];
+
+    let sponsor = address(20);
+
+    let result = env.bind_to_user_coded_name(keyring_address, user_coded_name);
+    assert_eq!(result, KeyringEvent::KeyringAccountSet);
+
+    let result: KeyringEvent = env.call_program_with_value(100, sponsor, "DepositSponsorship", (), 10 * UNITS);
+    assert_eq!(result, KeyringEvent::SponsorDeposited { sponsor, amount: 10 * UNITS, balance: 10 * UNITS });
+
+    let result: KeyringEvent = env.call(sponsor, "AssignBudget", (NAMESPACE, keyring_address, 10 * UNITS, 5 * UNITS, 100u32));
+    assert_eq!(result, KeyringEvent::BudgetAssigned { sponsor, keyring_address, remaining: 10 * UNITS });
+
+    let config = RecoveryConfig {
+        guardians: guardians.clone(),
+        threshold: 2,
+        delay_blocks: 5
+    };
+
+    // Must return an error (only the keyring account can set the guardians)
+    let result: KeyringEvent = env.call(guardians[0], "SetRecoveryConfig", (NAMESPACE, user_coded_name, config.clone()));
+    assert_eq!(result, KeyringEvent::Error(KeyringError::SessionHasInvalidCredentials));
+
+    let result: KeyringEvent = env.call(keyring_address, "SetRecoveryConfig", (NAMESPACE, user_coded_name, config.clone()));
+    assert_eq!(result, KeyringEvent::RecoveryConfigSet);
+
+    let result: KeyringQueryEvent = env.call(keyring_address, "RecoveryConfig", (NAMESPACE, user_coded_name));
+    assert_eq!(result, KeyringQueryEvent::RecoveryConfig(Some(config)));
+
+    let result: KeyringEvent = env.call(
+        guardians[0],
+        "InitiateRecovery",
+        (NAMESPACE, user_coded_name, new_keyring_address, keyring_data(new_keyring_address))
+    );
+    assert_eq!(result, KeyringEvent::RecoveryInitiated { new_keyring_address, executable_at: None });
+
+    // The current keyring account cancels the recovery
+    let result: KeyringEvent = env.call(keyring_address, "CancelRecovery", (NAMESPACE, user_coded_name));
+    assert_eq!(result, KeyringEvent::RecoveryCancelled);
+
+    let result: KeyringQueryEvent = env.call(keyring_address, "RecoveryRequest", (NAMESPACE, user_coded_name));
+    assert_eq!(result, KeyringQueryEvent::RecoveryRequest(None));
+
+    let result: KeyringEvent = env.call(
+        guardians[0],
+        "InitiateRecovery",
+        (NAMESPACE, user_coded_name, new_keyring_address, keyring_data(new_keyring_address))
+    );
+    assert_eq!(result, KeyringEvent::RecoveryInitiated { new_keyring_address, executable_at: None });
+
+    let result: KeyringEvent = env.call(guardians[1], "ApproveRecovery", (NAMESPACE, user_coded_name));
+    let KeyringEvent::RecoveryApproved { guardian, executable_at: Some(executable_at) } = result else {
+        core::panic!("Threshold must be reached: {result:?}");
+    };
+    assert_eq!(guardian, guardians[1]);
+
+    // Must return an error (timelock not expired)
+    let result: KeyringEvent = env.call(new_keyring_address, "FinalizeRecovery", (NAMESPACE, user_coded_name));
+    assert!(
+        matches!(result, KeyringEvent::Error(KeyringError::RecoveryTimelockNotExpired { .. })),
+        "Timelock must not be expired: {result:?}"
+    );
+
+    while env.system.block_height() < executable_at {
+        env.system.run_next_block();
+    }
+
+    let result: KeyringEvent = env.call(new_keyring_address, "FinalizeRecovery", (NAMESPACE, user_coded_name));
+    assert_eq!(result, KeyringEvent::RecoveryFinalized(new_keyring_address));
+    assert_eq!(
+        env.last_events(),
+        vec![(SERVICE, "BudgetMoved", NAMESPACE, keyring_address, new_keyring_address, 10 * UNITS).encode()]
+    );
+
+    let result: KeyringQueryEvent = env.call(keyring_address, "KeyringAddressFromUserCodedName", (NAMESPACE, user_coded_name));
+    assert_eq!(result, KeyringQueryEvent::KeyringAccountAddress(Some(new_keyring_address)));
+
+    // The sponsored budget follows the coded name
+    let result: KeyringQueryEvent = env.call(sponsor, "SponsoredBudget", (NAMESPACE, keyring_address));
+    assert_eq!(result, KeyringQueryEvent::SponsoredBudget(None));
+
+    let result: KeyringEvent = env.call(new_keyring_address, "RequestTopUp", (NAMESPACE, UNITS));
+    assert_eq!(result, KeyringEvent::ToppedUp { keyring_address: new_keyring_address, amount: UNITS, remaining: 9 * UNITS });
+
+    // The old keyring account is no longer valid
+    let result: KeyringQueryEvent = env.call(keyring_address, "KeyringAccountData", (NAMESPACE, keyring_address));
+    assert_eq!(result, KeyringQueryEvent::KeyringAccountData(None));
+
+    assert_eq!(env.invariants(), KeyringQueryEvent::InvariantsReport(Default::default()));
+}
+
+#[test]
+fn abort_recovery_when_the_new_keyring_account_is_bound() {
+    let env = Env::new();
+    let user_coded_name = "jsou3305bnsodheiJJCF9nc";
+    let keyring_address = address(2);
+    let new_keyring_address = address(3);
+    let guardians = vec![address(10), address(11)];
+
+    let result = env.bind_to_user_coded_name(keyring_address, user_coded_name);
+    assert_eq!(result, KeyringEvent::KeyringAccountSet);
+
+    let config = RecoveryConfig {
+        guardians: guardians.clone(),
+        threshold: 1,
+        delay_blocks: 0
+    };
+
+    let result: KeyringEvent = env.call(keyring_address, "SetRecoveryConfig", (NAMESPACE, user_coded_name, config));
+    assert_eq!(result, KeyringEvent::RecoveryConfigSet);
+
+    let result: KeyringEvent = env.call(
+        guardians[0],
+        "InitiateRecovery",
+        (NAMESPACE, user_coded_name, new_keyring_address, keyring_data(new_keyring_address))
+    );
+    assert!(matches!(result, KeyringEvent::RecoveryInitiated { .. }), "Recovery must be initiated: {result:?}");
+
+    // The new keyring account is bound to other coded name after the recovery started
+    let bindings = vec![(String::from("other"), new_keyring_address, keyring_data(new_keyring_address))];
+    let result: KeyringEvent = env.call(ADMIN.into(), "BindKeyringDataToUserCodedNames", (NAMESPACE, bindings));
+    assert_eq!(result, KeyringEvent::KeyringAccountsSet(vec![Ok(())]));
+
+    // Must return an error (the new keyring account is not free)
+    let result: KeyringEvent = env.call(new_keyring_address, "FinalizeRecovery", (NAMESPACE, user_coded_name));
+    assert_eq!(result, KeyringEvent::Error(KeyringError::KeyringAddressAlreadyEsists));
+
+    // Must return an error (only guardians can abort the recovery)
+    let result: KeyringEvent = env.call(address(4), "AbortRecovery", (NAMESPACE, user_coded_name));
+    assert_eq!(result, KeyringEvent::Error(KeyringError::ActionOnlyForGuardians));
+
+    let result: KeyringEvent = env.call(guardians[1], "AbortRecovery", (NAMESPACE, user_coded_name));
+    assert_eq!(result, KeyringEvent::RecoveryAborted);
+
+    // A new recovery can be initiated
+    let result: KeyringEvent = env.call(
+        guardians[0],
+        "InitiateRecovery",
+        (NAMESPACE, user_coded_name, address(5), keyring_data(address(5)))
+    );
+    assert!(matches!(result, KeyringEvent::RecoveryInitiated { .. }), "Recovery must be initiated: {result:?}");
+
+    assert_eq!(env.invariants(), KeyringQueryEvent::InvariantsReport(Default::default()));
+}
+
+// Small pools of addresses and coded names, so the random sequences
+// generate a lot of conflicts between the bindings
+#[derive(Debug, Clone)]
+enum Operation {
+    BindUserAddress {
+        keyring_address: u64,
+        user_address: u64
+    },
+    BindUserCodedName {
+        keyring_address: u64,
+        user_coded_name: u8
+    }
+}
+
+fn operation() -> impl Strategy<Value = Operation> {
+    prop_oneof![
+        (1u64..6, 1u64..6).prop_map(|(keyring_address, user_address)| {
+            Operation::BindUserAddress { keyring_address, user_address }
+        }),
+        (1u64..6, 0u8..3).prop_map(|(keyring_address, user_coded_name)| {
+            Operation::BindUserCodedName { keyring_address, user_coded_name }
+        })
+    ]
+}
+
+proptest! {
+    // Each case deploys a new program, so the amount of cases is kept low
+    #![proptest_config(ProptestConfig::with_cases(8))]
+
+    #[test]
+    fn random_bindings_keep_state_consistent(operations in prop::collection::vec(operation(), 1..16)) {
+        let env = Env::new();
+        // Same operations applied to the state outside of the program
+        let mut expected_state = KeyringRegistry::default();
+
+        for operation in operations {
+            let (result, expected) = match operation {
+                Operation::BindUserAddress { keyring_address, user_address } => {
+                    let (keyring_address, user_address) = (address(keyring_address), address(user_address));
+
+                    (
+                        env.bind_to_user_address(keyring_address, user_address),
+                        expected_state.set_keyring_account_to_user_address(
+                            keyring_address,
+                            user_address,
+                            keyring_data(keyring_address)
+                        )
+                    )
+                },
+                Operation::BindUserCodedName { keyring_address, user_coded_name } => {
+                    let keyring_address = address(keyring_address);
+                    let user_coded_name = format!("coded-name-{user_coded_name}");
+
+                    (
+                        env.bind_to_user_coded_name(keyring_address, &user_coded_name),
+                        expected_state.set_keyring_account_to_user_coded_name(
+                            keyring_address,
+                            user_coded_name,
+                            keyring_data(keyring_address)
+                        )
+                    )
+                }
+            };
+
+            let expected = match expected {
+                Ok(_) => KeyringEvent::KeyringAccountSet,
+                Err(keyring_error) => KeyringEvent::Error(keyring_error)
+            };
+
+            prop_assert_eq!(result, expected);
+        }
+
+        prop_assert!(expected_state.validate_invariants().is_consistent());
+        prop_assert_eq!(env.invariants(), KeyringQueryEvent::InvariantsReport(Default::default()));
+    }
+}
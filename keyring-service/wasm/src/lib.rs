@@ -0,0 +1,36 @@
+#![no_std]
+
+// # Example program that exposes the keyring service
+// Used by the gtest integration tests of the keyring service
+
+use sails_rs::prelude::*;
+use keyring_service::services::keyring_service::KeyringService;
+
+#[derive(Default)]
+pub struct Program;
+
+#[program]
+impl Program {
+    // Program constructor, the source of the init message will be the admin
+    pub fn new() -> Self {
+        KeyringService::seed();
+
+        Self
+    }
+
+    // Keyring service
+    #[export(route = "KeyringService")]
+    pub fn keyring_svc(&self) -> KeyringService {
+        KeyringService::new()
+    }
+}
+
+#[cfg(feature = "wasm-binary")]
+#[cfg(not(target_arch = "wasm32"))]
+pub use code::WASM_BINARY_OPT as WASM_BINARY;
+
+#[cfg(feature = "wasm-binary")]
+#[cfg(not(target_arch = "wasm32"))]
+mod code {
+    include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
+}